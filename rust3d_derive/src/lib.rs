@@ -0,0 +1,277 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitInt, LitStr};
+
+/// Derive `crate::vertex::VertexFormat` for a `#[repr(C)]` struct.
+///
+/// Each field becomes one vertex attribute. The attribute location is the field index
+/// unless it is given by `#[vertex(location = N)]`, and integer fields can be read
+/// as normalized floats with `#[vertex(normalized)]`.
+#[proc_macro_derive(VertexFormat, attributes(vertex))]
+pub fn derive_vertex_format(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    return match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    };
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    // Field offsets are only meaningful with C layout
+    let mut is_repr_c = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                is_repr_c = true;
+            }
+            Ok(())
+        })?;
+    }
+    if !is_repr_c {
+        return Err(syn::Error::new_spanned(
+            name,
+            "VertexFormat requires #[repr(C)]",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "VertexFormat requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "VertexFormat can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut attributes = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let mut location = index as u32;
+        let mut normalized = false;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("vertex"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("location") {
+                    location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    Ok(())
+                } else if meta.path.is_ident("normalized") {
+                    normalized = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `location = N` or `normalized`"))
+                }
+            })?;
+        }
+
+        let field_name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        attributes.push(quote! {
+            crate::vertex::VertexAttribute {
+                location: #location,
+                gl_type: <#ty as crate::vertex::AttributeType>::GL_TYPE,
+                components: <#ty as crate::vertex::AttributeType>::COMPONENTS,
+                locations: <#ty as crate::vertex::AttributeType>::LOCATIONS,
+                normalized: #normalized,
+                offset: ::core::mem::offset_of!(#name, #field_name),
+            }
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    return Ok(quote! {
+        impl #impl_generics crate::vertex::VertexFormat for #name #ty_generics #where_clause {
+            fn attributes() -> ::std::vec::Vec<crate::vertex::VertexAttribute> {
+                ::std::vec![#(#attributes),*]
+            }
+        }
+    });
+}
+
+/// Derive `crate::shader::UniformStruct` for a struct mirroring a GLSL struct.
+///
+/// Each field is set as the uniform `<name>.<field>`. The GLSL member name can be
+/// given with `#[uniform(name = "...")]` when it differs from the field name.
+#[proc_macro_derive(UniformStruct, attributes(uniform))]
+pub fn derive_uniform_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    return match expand_uniform_struct(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    };
+}
+
+fn expand_uniform_struct(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "UniformStruct requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "UniformStruct can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut members = Vec::new();
+    let mut setters = Vec::new();
+    let mut longest_member = 0usize;
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let mut member = field_name.to_string();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("uniform"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    member = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `name = \"...\"`"))
+                }
+            })?;
+        }
+
+        // Member names are static, appended to `<name>.` in one buffer
+        let index = members.len();
+        longest_member = longest_member.max(member.len());
+        setters.push(quote! {
+            uniform.truncate(prefix_len);
+            uniform.push_str(Self::MEMBERS[#index]);
+            shader.set_uniform_str(&uniform, ::core::slice::from_ref(&self.#field_name));
+        });
+        members.push(member);
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    return Ok(quote! {
+        impl #impl_generics crate::shader::UniformStruct for #name #ty_generics #where_clause {
+            const MEMBERS: &'static [&'static str] = &[#(#members),*];
+
+            unsafe fn set_fields(&self, shader: &crate::shader::Shader, name: &str) {
+                let mut uniform =
+                    ::std::string::String::with_capacity(name.len() + 1 + #longest_member);
+                uniform.push_str(name);
+                uniform.push('.');
+                let prefix_len = uniform.len();
+                #(#setters)*
+            }
+        }
+    });
+}
+
+/// Derive `crate::uniform_buffer::Std140` for a struct mirroring a GLSL uniform block
+/// or struct. Fields are laid out in declaration order with std140 alignment rules.
+#[proc_macro_derive(Std140)]
+pub fn derive_std140(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    return match expand_std140(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    };
+}
+
+fn expand_std140(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "Std140 requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "Std140 can only be derived for structs",
+            ))
+        }
+    };
+
+    let std140 = quote! { crate::uniform_buffer::Std140 };
+    let align_to = quote! { crate::uniform_buffer::align_to };
+
+    // Offset of each field, computed from the end of the previous one
+    let mut offsets = Vec::new();
+    let mut writes = Vec::new();
+    let mut aligns = Vec::new();
+    let mut end = quote! { 0 };
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let offset = format_ident!("offset_{}", index);
+
+        offsets.push(quote! {
+            let #offset = #align_to(#end, <#ty as #std140>::ALIGN);
+        });
+        writes.push(quote! {
+            #std140::write_std140(&self.#field_name, &mut out[#offset..]);
+        });
+        aligns.push(quote! {
+            if <#ty as #std140>::ALIGN > align {
+                align = <#ty as #std140>::ALIGN;
+            }
+        });
+        end = quote! { #offset + <#ty as #std140>::SIZE };
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    return Ok(quote! {
+        impl #impl_generics #std140 for #name #ty_generics #where_clause {
+            // Structs are aligned to at least a vec4
+            const ALIGN: usize = {
+                let mut align = 16;
+                #(#aligns)*
+                align
+            };
+            const SIZE: usize = {
+                #(#offsets)*
+                #align_to(#end, <Self as #std140>::ALIGN)
+            };
+
+            fn write_std140(&self, out: &mut [u8]) {
+                #(#offsets)*
+                #(#writes)*
+            }
+        }
+    });
+}
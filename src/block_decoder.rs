@@ -0,0 +1,981 @@
+//! CPU decoders of 4x4 compressed blocks, used when the driver lacks a format.
+//! Pixels are returned row by row, from the top left of the block.
+
+/// 4x4 pixels of 8-bit RGBA
+pub type Block = [[u8; 4]; 16];
+
+/// BC1 (DXT1). With `alpha`, the 3-color mode has transparent black instead of opaque black.
+pub fn decode_bc1(block: &[u8], alpha: bool) -> Block {
+    return decode_bc1_color(block, alpha, false);
+}
+
+/// BC2 (DXT3): BC1 colors with explicit 4-bit alpha
+pub fn decode_bc2(block: &[u8]) -> Block {
+    let mut pixels = decode_bc1_color(&block[8..16], false, true);
+    let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        pixel[3] = ((alpha >> (4 * i)) & 0xF) as u8 * 17;
+    }
+
+    return pixels;
+}
+
+/// BC3 (DXT5): BC1 colors with BC4 alpha
+pub fn decode_bc3(block: &[u8]) -> Block {
+    let mut pixels = decode_bc1_color(&block[8..16], false, true);
+    let alpha = decode_bc4_unorm(&block[0..8]);
+    for (pixel, alpha) in pixels.iter_mut().zip(alpha) {
+        pixel[3] = alpha;
+    }
+
+    return pixels;
+}
+
+/// Colors of a BC1, BC2 or BC3 block. BC2 and BC3 always use the 4-color mode.
+fn decode_bc1_color(block: &[u8], alpha: bool, four_colors: bool) -> Block {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let c0 = expand_565(color0);
+    let c1 = expand_565(color1);
+    let mut palette = [c0, c1, [0, 0, 0, 255], [0, 0, 0, 255]];
+    if color0 > color1 || four_colors {
+        for channel in 0..3 {
+            let (a, b) = (c0[channel] as u32, c1[channel] as u32);
+            palette[2][channel] = ((2 * a + b + 1) / 3) as u8;
+            palette[3][channel] = ((a + 2 * b + 1) / 3) as u8;
+        }
+    } else {
+        for channel in 0..3 {
+            palette[2][channel] = (c0[channel] as u32 + c1[channel] as u32).div_ceil(2) as u8;
+        }
+        if alpha {
+            palette[3] = [0, 0, 0, 0];
+        }
+    }
+
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+
+    return pixels;
+}
+
+fn expand_565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 31) as u8;
+    let g = ((color >> 5) & 63) as u8;
+    let b = (color & 31) as u8;
+
+    return [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ];
+}
+
+/// One channel of BC4 (the alpha of BC3, each channel of BC5)
+pub fn decode_bc4_unorm(block: &[u8]) -> [u8; 16] {
+    let (e0, e1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [e0, e1, 0, 0, 0, 0, 0, 0];
+    if e0 > e1 {
+        for (i, value) in palette.iter_mut().enumerate().skip(2) {
+            *value = ((8 - i as u32) * e0 + (i as u32 - 1) * e1 + 3) / 7;
+        }
+    } else {
+        for (i, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            *value = ((6 - i as u32) * e0 + (i as u32 - 1) * e1 + 2) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    return bc4_indices(block).map(|index| palette[index] as u8);
+}
+
+/// One channel of signed BC4, from -1 to 1
+pub fn decode_bc4_snorm(block: &[u8]) -> [f32; 16] {
+    // -128 is read as -127 so that both ends are symmetric
+    let e0 = (block[0] as i8).max(-127) as f32;
+    let e1 = (block[1] as i8).max(-127) as f32;
+    let mut palette = [e0, e1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    if e0 > e1 {
+        for (i, value) in palette.iter_mut().enumerate().skip(2) {
+            *value = ((8 - i) as f32 * e0 + (i - 1) as f32 * e1) / 7.0;
+        }
+    } else {
+        for (i, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            *value = ((6 - i) as f32 * e0 + (i - 1) as f32 * e1) / 5.0;
+        }
+        palette[6] = -127.0;
+        palette[7] = 127.0;
+    }
+
+    return bc4_indices(block).map(|index| palette[index] / 127.0);
+}
+
+/// 3-bit palette indices stored after the two endpoints
+fn bc4_indices(block: &[u8]) -> [usize; 16] {
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (8 * i);
+    }
+
+    let mut indices = [0; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        *index = ((bits >> (3 * i)) & 7) as usize;
+    }
+
+    return indices;
+}
+
+/// Bits of a BC7 block read from the least significant bit up
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> BitReader {
+        return BitReader {
+            bits: u128::from_le_bytes(block[0..16].try_into().unwrap()),
+            position: 0,
+        };
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = ((self.bits >> self.position) & ((1u128 << count) - 1)) as u32;
+        self.position += count;
+
+        return value;
+    }
+}
+
+/// Layout of one of the 8 BC7 modes
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// P-bit per endpoint
+    endpoint_pbits: bool,
+    /// P-bit per subset, shared by its two endpoints
+    shared_pbits: bool,
+    index_bits: u32,
+    /// Bits of the separate alpha indices, 0 if color and alpha share indices
+    index2_bits: u32,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index2_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index2_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+];
+
+/// Subset of each pixel in the 2-subset partitions, one bit per pixel
+#[rustfmt::skip]
+const BC7_PARTITIONS2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Subset of each pixel in the 3-subset partitions
+#[rustfmt::skip]
+const BC7_PARTITIONS3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Pixel of subset 1 whose index has one bit less, in the 2-subset partitions
+#[rustfmt::skip]
+const BC7_ANCHORS2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor pixels of subsets 1 and 2 in the 3-subset partitions
+#[rustfmt::skip]
+const BC7_ANCHORS3: [[usize; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const BC7_WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// BC7. Reserved mode 8 decodes to transparent black, as the format specifies.
+pub fn decode_bc7(block: &[u8]) -> Block {
+    let mut reader = BitReader::new(block);
+    let mode_index = match (0..8).find(|_| reader.read(1) == 1) {
+        Some(mode_index) => mode_index,
+        None => return [[0; 4]; 16],
+    };
+    let mode = &BC7_MODES[mode_index];
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // Endpoints of each subset, channel by channel
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    for channel in 0..4 {
+        let bits = if channel < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = reader.read(bits);
+            }
+        }
+    }
+
+    let mut pbits = [[0u32; 2]; 3];
+    if mode.endpoint_pbits {
+        for subset in pbits.iter_mut().take(mode.subsets) {
+            subset[0] = reader.read(1);
+            subset[1] = reader.read(1);
+        }
+    } else if mode.shared_pbits {
+        for subset in pbits.iter_mut().take(mode.subsets) {
+            let pbit = reader.read(1);
+            *subset = [pbit, pbit];
+        }
+    }
+    let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+
+    // Expand endpoints to 8 bits, with the P-bit as the lowest bit
+    for subset in 0..mode.subsets {
+        for endpoint in 0..2 {
+            let pbit = pbits[subset][endpoint];
+            for (channel, value) in endpoints[subset][endpoint].iter_mut().enumerate() {
+                let mut bits = if channel < 3 {
+                    mode.color_bits
+                } else {
+                    mode.alpha_bits
+                };
+                if bits == 0 {
+                    *value = 255;
+                    continue;
+                }
+
+                if has_pbits {
+                    *value = (*value << 1) | pbit;
+                    bits += 1;
+                }
+                *value = (*value << (8 - bits)) | (*value >> (2 * bits - 8));
+            }
+        }
+    }
+
+    let subset_of = |pixel: usize| -> usize {
+        return match mode.subsets {
+            1 => 0,
+            2 => ((BC7_PARTITIONS2[partition] >> pixel) & 1) as usize,
+            _ => BC7_PARTITIONS3[partition][pixel] as usize,
+        };
+    };
+    let is_anchor = |pixel: usize| -> bool {
+        return match mode.subsets {
+            1 => pixel == 0,
+            2 => pixel == 0 || pixel == BC7_ANCHORS2[partition],
+            _ => {
+                pixel == 0
+                    || pixel == BC7_ANCHORS3[0][partition]
+                    || pixel == BC7_ANCHORS3[1][partition]
+            }
+        };
+    };
+
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let bits = if is_anchor(pixel) {
+            mode.index_bits - 1
+        } else {
+            mode.index_bits
+        };
+        *index = reader.read(bits);
+    }
+    let mut indices2 = [0u32; 16];
+    if mode.index2_bits > 0 {
+        for (pixel, index) in indices2.iter_mut().enumerate() {
+            let bits = if pixel == 0 {
+                mode.index2_bits - 1
+            } else {
+                mode.index2_bits
+            };
+            *index = reader.read(bits);
+        }
+    }
+
+    let weights = |bits: u32| -> &[u32] {
+        return match bits {
+            2 => &BC7_WEIGHTS2,
+            3 => &BC7_WEIGHTS3,
+            _ => &BC7_WEIGHTS4,
+        };
+    };
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let [e0, e1] = endpoints[subset_of(i)];
+
+        // Color and alpha weights, swapped by the index selection bit
+        let (color_weight, alpha_weight) = if mode.index2_bits == 0 {
+            let weight = weights(mode.index_bits)[indices[i] as usize];
+            (weight, weight)
+        } else if index_selection == 0 {
+            (
+                weights(mode.index_bits)[indices[i] as usize],
+                weights(mode.index2_bits)[indices2[i] as usize],
+            )
+        } else {
+            (
+                weights(mode.index2_bits)[indices2[i] as usize],
+                weights(mode.index_bits)[indices[i] as usize],
+            )
+        };
+
+        for channel in 0..4 {
+            let weight = if channel < 3 {
+                color_weight
+            } else {
+                alpha_weight
+            };
+            pixel[channel] = (((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6) as u8;
+        }
+
+        match rotation {
+            1 => pixel.swap(0, 3),
+            2 => pixel.swap(1, 3),
+            3 => pixel.swap(2, 3),
+            _ => {}
+        }
+    }
+
+    return pixels;
+}
+
+/// Modifier pairs of the ETC1 and ETC2 individual and differential modes
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances of the ETC2 T and H modes
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// ETC2 RGB, or RGB with punch-through alpha if `punchthrough`.
+/// ETC1 blocks are ETC2 blocks that do not use the T, H or planar modes.
+pub fn decode_etc2_rgb(block: &[u8], punchthrough: bool) -> Block {
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let field = |high, count| etc_field(bits, high, count);
+
+    // Without the individual mode in punch-through blocks, this bit says whether the block is opaque
+    let differential = punchthrough || field(33, 1) == 1;
+    let opaque = !punchthrough || field(33, 1) == 1;
+
+    if differential {
+        let base = [field(63, 5), field(55, 5), field(47, 5)];
+        let delta =
+            [field(58, 3), field(50, 3), field(42, 3)].map(|d| if d >= 4 { d - 8 } else { d });
+        let second = [base[0] + delta[0], base[1] + delta[1], base[2] + delta[2]];
+
+        // An overflowing channel selects one of the modes ETC2 added
+        if !(0..32).contains(&second[0]) {
+            return decode_etc2_t(bits, opaque);
+        }
+        if !(0..32).contains(&second[1]) {
+            return decode_etc2_h(bits, opaque);
+        }
+        if !(0..32).contains(&second[2]) {
+            return decode_etc2_planar(bits);
+        }
+
+        let colors = [base.map(extend_5), second.map(extend_5)];
+        return decode_etc_subblocks(bits, colors, opaque);
+    }
+
+    let colors = [
+        [field(63, 4), field(55, 4), field(47, 4)].map(|c| c * 17),
+        [field(59, 4), field(51, 4), field(43, 4)].map(|c| c * 17),
+    ];
+
+    return decode_etc_subblocks(bits, colors, true);
+}
+
+/// ETC2 RGBA: EAC alpha followed by ETC2 RGB
+pub fn decode_etc2_rgba(block: &[u8]) -> Block {
+    let mut pixels = decode_etc2_rgb(&block[8..16], false);
+    let alpha = decode_eac(&block[0..8]);
+    for (pixel, alpha) in pixels.iter_mut().zip(alpha) {
+        pixel[3] = alpha;
+    }
+
+    return pixels;
+}
+
+/// `count` bits of the block ending at bit `high`, numbered from the least significant bit
+fn etc_field(bits: u64, high: u32, count: u32) -> i32 {
+    return ((bits >> (high + 1 - count)) & ((1 << count) - 1)) as i32;
+}
+
+fn extend_5(value: i32) -> i32 {
+    return (value << 3) | (value >> 2);
+}
+
+/// Position of the pixel in the row-major output, from the column-major index order of ETC
+fn etc_pixel(index: usize) -> usize {
+    return (index % 4) * 4 + index / 4;
+}
+
+/// 2-bit index of the pixel with the column-major `index`
+fn etc_index(bits: u64, index: usize) -> usize {
+    let msb = (bits >> (16 + index)) & 1;
+    let lsb = (bits >> index) & 1;
+
+    return (msb * 2 + lsb) as usize;
+}
+
+/// Individual and differential modes: two half-blocks with a base color and modifier table each
+fn decode_etc_subblocks(bits: u64, colors: [[i32; 3]; 2], opaque: bool) -> Block {
+    let flip = (bits >> 32) & 1 == 1;
+    let tables = [((bits >> 37) & 7) as usize, ((bits >> 34) & 7) as usize];
+
+    let mut pixels = [[0u8; 4]; 16];
+    for index in 0..16 {
+        let (x, y) = (index / 4, index % 4);
+        let subblock = if flip {
+            (y >= 2) as usize
+        } else {
+            (x >= 2) as usize
+        };
+        let [small, large] = ETC_MODIFIERS[tables[subblock]];
+        let pixel_index = etc_index(bits, index);
+
+        // Punch-through blocks that are not opaque use index 2 for transparency
+        // and drop the small modifiers
+        if !opaque && pixel_index == 2 {
+            pixels[etc_pixel(index)] = [0, 0, 0, 0];
+            continue;
+        }
+        let modifier = match (pixel_index, opaque) {
+            (0, true) => small,
+            (1, _) => large,
+            (2, _) => -small,
+            (3, _) => -large,
+            _ => 0,
+        };
+
+        let color = colors[subblock].map(|c| (c + modifier).clamp(0, 255) as u8);
+        pixels[etc_pixel(index)] = [color[0], color[1], color[2], 255];
+    }
+
+    return pixels;
+}
+
+fn decode_etc2_t(bits: u64, opaque: bool) -> Block {
+    let field = |high, count| etc_field(bits, high, count);
+    let color1 = [
+        (field(60, 2) << 2) | field(57, 2),
+        field(55, 4),
+        field(51, 4),
+    ]
+    .map(|c| c * 17);
+    let color2 = [field(47, 4), field(43, 4), field(39, 4)].map(|c| c * 17);
+    let distance = ETC_DISTANCES[((field(35, 2) << 1) | field(32, 1)) as usize];
+
+    let paint = [
+        color1,
+        color2.map(|c| c + distance),
+        color2,
+        color2.map(|c| c - distance),
+    ];
+
+    return decode_etc_paint(bits, paint, opaque);
+}
+
+fn decode_etc2_h(bits: u64, opaque: bool) -> Block {
+    let field = |high, count| etc_field(bits, high, count);
+    let red1 = field(62, 4);
+    let green1 = (field(58, 3) << 1) | field(52, 1);
+    let blue1 = (field(51, 1) << 3) | field(49, 3);
+    let (red2, green2, blue2) = (field(46, 4), field(42, 4), field(38, 4));
+
+    // The order of the two colors holds the lowest bit of the distance index
+    let order =
+        (((red1 << 8) | (green1 << 4) | blue1) >= ((red2 << 8) | (green2 << 4) | blue2)) as i32;
+    let distance = ETC_DISTANCES[((field(34, 1) << 2) | (field(32, 1) << 1) | order) as usize];
+
+    let color1 = [red1, green1, blue1].map(|c| c * 17);
+    let color2 = [red2, green2, blue2].map(|c| c * 17);
+    let paint = [
+        color1.map(|c| c + distance),
+        color1.map(|c| c - distance),
+        color2.map(|c| c + distance),
+        color2.map(|c| c - distance),
+    ];
+
+    return decode_etc_paint(bits, paint, opaque);
+}
+
+/// T and H modes: each pixel picks one of four paint colors
+fn decode_etc_paint(bits: u64, paint: [[i32; 3]; 4], opaque: bool) -> Block {
+    let mut pixels = [[0u8; 4]; 16];
+    for index in 0..16 {
+        let pixel_index = etc_index(bits, index);
+        pixels[etc_pixel(index)] = if !opaque && pixel_index == 2 {
+            [0, 0, 0, 0]
+        } else {
+            let color = paint[pixel_index].map(|c| c.clamp(0, 255) as u8);
+            [color[0], color[1], color[2], 255]
+        };
+    }
+
+    return pixels;
+}
+
+/// Planar mode: colors interpolated from the corners at the origin, right and bottom
+fn decode_etc2_planar(bits: u64) -> Block {
+    let field = |high, count| etc_field(bits, high, count);
+    let extend_6 = |c: i32| (c << 2) | (c >> 4);
+    let extend_7 = |c: i32| (c << 1) | (c >> 6);
+
+    let origin = [
+        extend_6(field(62, 6)),
+        extend_7((field(56, 1) << 6) | field(54, 6)),
+        extend_6((field(48, 1) << 5) | (field(44, 2) << 3) | field(41, 3)),
+    ];
+    let horizontal = [
+        extend_6((field(38, 5) << 1) | field(32, 1)),
+        extend_7(field(31, 7)),
+        extend_6(field(24, 6)),
+    ];
+    let vertical = [
+        extend_6(field(18, 6)),
+        extend_7(field(12, 7)),
+        extend_6(field(5, 6)),
+    ];
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        for channel in 0..3 {
+            let value = (x * (horizontal[channel] - origin[channel])
+                + y * (vertical[channel] - origin[channel])
+                + 4 * origin[channel]
+                + 2)
+                >> 2;
+            pixel[channel] = value.clamp(0, 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+
+    return pixels;
+}
+
+#[rustfmt::skip]
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// 8-bit EAC channel (the alpha of ETC2 RGBA)
+fn decode_eac(block: &[u8]) -> [u8; 16] {
+    let bits = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let base = (bits >> 56) as i32;
+    let multiplier = ((bits >> 52) & 0xF) as i32;
+    let modifiers = EAC_MODIFIERS[((bits >> 48) & 0xF) as usize];
+
+    let mut values = [0u8; 16];
+    for index in 0..16 {
+        let modifier = modifiers[((bits >> (45 - 3 * index)) & 7) as usize];
+        values[etc_pixel(index)] = (base + modifier * multiplier).clamp(0, 255) as u8;
+    }
+
+    return values;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// BC7 block from `(bit count, value)` fields, written from the least significant bit up
+    fn bc7_block(fields: &[(u32, u32)]) -> [u8; 16] {
+        let mut bits = 0u128;
+        let mut position = 0;
+        for (count, value) in fields {
+            assert!(*value < (1 << count));
+            bits |= (*value as u128) << position;
+            position += count;
+        }
+        assert_eq!(position, 128);
+
+        return bits.to_le_bytes();
+    }
+
+    /// `value` placed in the `count` bits ending at bit `high` of an ETC block
+    fn etc_bits(value: u64, high: u32, count: u32) -> u64 {
+        assert!(value < (1 << count));
+        return value << (high + 1 - count);
+    }
+
+    /// ETC pixel index bits, with `index_of(x, y)` for each pixel
+    fn etc_indices(index_of: impl Fn(usize, usize) -> u64) -> u64 {
+        let mut bits = 0;
+        for k in 0..16 {
+            // Pixels are numbered column by column
+            let index = index_of(k / 4, k % 4);
+            bits |= ((index >> 1) << (16 + k)) | ((index & 1) << k);
+        }
+
+        return bits;
+    }
+
+    /// BC4 palette index `i % 8` for pixel `i`
+    pub(crate) fn bc4_block(e0: u8, e1: u8) -> [u8; 8] {
+        let mut bits = 0u64;
+        for i in 0..16 {
+            bits |= (i % 8) << (3 * i);
+        }
+        let indices = bits.to_le_bytes();
+
+        return [
+            e0, e1, indices[0], indices[1], indices[2], indices[3], indices[4], indices[5],
+        ];
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red and blue, with pixel indices 0, 1, 2, 3 in every row
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4];
+        let pixels = decode_bc1(&block, true);
+
+        for row in pixels.chunks(4) {
+            assert_eq!(row[0], [255, 0, 0, 255]);
+            assert_eq!(row[1], [0, 0, 255, 255]);
+            assert_eq!(row[2], [170, 0, 85, 255]);
+            assert_eq!(row[3], [85, 0, 170, 255]);
+        }
+    }
+
+    #[test]
+    fn bc1_three_colors() {
+        // color0 <= color1 selects the mode with the midpoint and black
+        let block = [0x1F, 0x00, 0x00, 0xF8, 0xE4, 0xE4, 0xE4, 0xE4];
+        let opaque = decode_bc1(&block, false);
+        let alpha = decode_bc1(&block, true);
+
+        assert_eq!(opaque[0], [0, 0, 255, 255]);
+        assert_eq!(opaque[1], [255, 0, 0, 255]);
+        assert_eq!(opaque[2], [128, 0, 128, 255]);
+        assert_eq!(opaque[3], [0, 0, 0, 255]);
+        assert_eq!(alpha[2], [128, 0, 128, 255]);
+        assert_eq!(alpha[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc4_eight_values() {
+        let values = decode_bc4_unorm(&bc4_block(255, 0));
+
+        let palette = [255, 0, 219, 182, 146, 109, 73, 36];
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(*value, palette[i % 8], "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc4_six_values() {
+        // e0 <= e1 interpolates 4 values and adds 0 and 255
+        let values = decode_bc4_unorm(&bc4_block(0, 255));
+
+        let palette = [0, 255, 51, 102, 153, 204, 0, 255];
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(*value, palette[i % 8], "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc4_signed_endpoints() {
+        // -128 is clamped to -127
+        let values = decode_bc4_snorm(&bc4_block(127, 0x80));
+
+        assert_eq!(values[0], 1.0);
+        assert_eq!(values[1], -1.0);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        // Partition 0 puts columns 2 and 3 in subset 1, whose anchor is pixel 15
+        let mut indices = vec![(2, 0), (3, 0), (3, 7), (3, 0), (3, 0), (3, 0), (3, 3)];
+        indices.extend([(3, 0); 8]);
+        indices.push((2, 3));
+
+        let mut fields = vec![(2, 0b10), (6, 0)];
+        // Red of subset 0, then red, green and blue of subset 1
+        fields.extend([(6, 63), (6, 63), (6, 0), (6, 63)]);
+        fields.extend([(6, 0), (6, 0), (6, 0), (6, 63)]);
+        fields.extend([(6, 0), (6, 0), (6, 0), (6, 63)]);
+        // Shared P-bits
+        fields.extend([(1, 1), (1, 1)]);
+        fields.extend(indices);
+        let pixels = decode_bc7(&bc7_block(&fields));
+
+        // The P-bit is the lowest of 7 bits, expanded to 8 by repeating the top bit
+        assert_eq!(pixels[0], [255, 2, 2, 255]);
+        assert_eq!(pixels[1], [255, 2, 2, 255]);
+        assert_eq!(pixels[2], [255, 255, 255, 255]);
+        assert_eq!(pixels[3], [2, 2, 2, 255]);
+        assert_eq!(pixels[6], [109, 109, 109, 255]);
+        assert_eq!(pixels[14], [2, 2, 2, 255]);
+        assert_eq!(pixels[15], [109, 109, 109, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_5() {
+        let block = |rotation| {
+            let mut fields = vec![(6, 0b100000), (2, rotation)];
+            fields.extend([(7, 0), (7, 127), (7, 0), (7, 127), (7, 0), (7, 127)]);
+            fields.extend([(8, 255), (8, 0)]);
+            // Color indices, then alpha indices, with 1 bit for the anchor pixel 0
+            fields.extend([(1, 1), (2, 3), (2, 2)]);
+            fields.extend([(2, 0); 13]);
+            fields.extend([(1, 0), (2, 3), (2, 1)]);
+            fields.extend([(2, 0); 13]);
+            bc7_block(&fields)
+        };
+
+        let pixels = decode_bc7(&block(0));
+        assert_eq!(pixels[0], [84, 84, 84, 255]);
+        assert_eq!(pixels[1], [255, 255, 255, 0]);
+        assert_eq!(pixels[2], [171, 171, 171, 171]);
+        assert_eq!(pixels[3], [0, 0, 0, 255]);
+
+        // Rotation 1 swaps red and alpha
+        let rotated = decode_bc7(&block(1));
+        assert_eq!(rotated[0], [255, 84, 84, 84]);
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        let mut fields = vec![(7, 0b1000000)];
+        // Red, green, blue and alpha of both endpoints
+        fields.extend([
+            (7, 0),
+            (7, 127),
+            (7, 0),
+            (7, 64),
+            (7, 0),
+            (7, 0),
+            (7, 127),
+            (7, 127),
+        ]);
+        fields.extend([(1, 0), (1, 1)]);
+        fields.extend([(3, 0), (4, 15), (4, 8)]);
+        fields.extend([(4, 0); 13]);
+        let pixels = decode_bc7(&bc7_block(&fields));
+
+        assert_eq!(pixels[0], [0, 0, 0, 254]);
+        assert_eq!(pixels[1], [255, 129, 1, 255]);
+        assert_eq!(pixels[2], [135, 69, 1, 255]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_transparent_black() {
+        let mut block = [0xFF; 16];
+        block[0] = 0;
+
+        assert_eq!(decode_bc7(&block), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red 31 + 1 overflows in the differential mode
+        let bits = etc_bits(0b111, 63, 3)
+            | etc_bits(0b11, 60, 2)
+            | etc_bits(0b01, 57, 2)
+            | etc_bits(4, 55, 4)
+            | etc_bits(8, 51, 4)
+            | etc_bits(2, 47, 4)
+            | etc_bits(6, 43, 4)
+            | etc_bits(10, 39, 4)
+            | etc_bits(0b01, 35, 2)
+            | etc_bits(1, 33, 1)
+            | etc_bits(1, 32, 1)
+            | etc_indices(|_, y| y as u64);
+        let pixels = decode_etc2_rgb(&bits.to_be_bytes(), false);
+
+        // Paint colors: color 1, then color 2 plus, without and minus distance 16
+        let paint = [
+            [221, 68, 136],
+            [50, 118, 186],
+            [34, 102, 170],
+            [18, 86, 154],
+        ];
+        for (i, pixel) in pixels.iter().enumerate() {
+            let [r, g, b] = paint[i / 4];
+            assert_eq!(*pixel, [r, g, b, 255], "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green 31 + 1 overflows in the differential mode, red does not
+        let bits = etc_bits(8, 62, 4)
+            | etc_bits(0b011, 58, 3)
+            | etc_bits(0b111, 55, 3)
+            | etc_bits(1, 52, 1)
+            | etc_bits(1, 51, 1)
+            | etc_bits(0b010, 49, 3)
+            | etc_bits(4, 46, 4)
+            | etc_bits(5, 42, 4)
+            | etc_bits(6, 38, 4)
+            | etc_bits(1, 34, 1)
+            | etc_bits(1, 33, 1)
+            | etc_indices(|_, y| y as u64);
+        let pixels = decode_etc2_rgb(&bits.to_be_bytes(), false);
+
+        // Color 1 is larger than color 2, which sets the lowest bit of distance index 5
+        let paint = [
+            [168, 151, 202],
+            [104, 87, 138],
+            [100, 117, 134],
+            [36, 53, 70],
+        ];
+        for (i, pixel) in pixels.iter().enumerate() {
+            let [r, g, b] = paint[i / 4];
+            assert_eq!(*pixel, [r, g, b, 255], "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn etc2_planar_mode() {
+        // Blue 31 + 1 overflows in the differential mode, red and green do not
+        let bits = etc_bits(32, 62, 6)
+            | etc_bits(32, 54, 6)
+            | etc_bits(0b111, 47, 3)
+            | etc_bits(0b11, 44, 2)
+            | etc_bits(0b010, 41, 3)
+            | etc_bits(0b11111, 38, 5)
+            | etc_bits(1, 33, 1)
+            | etc_bits(1, 32, 1)
+            | etc_bits(63, 24, 6)
+            | etc_bits(127, 12, 7);
+        let pixels = decode_etc2_rgb(&bits.to_be_bytes(), false);
+
+        // Origin (130, 64, 105), horizontal (255, 0, 255), vertical (0, 255, 0)
+        assert_eq!(pixels[0], [130, 64, 105, 255]);
+        assert_eq!(pixels[3], [224, 16, 218, 255]);
+        assert_eq!(pixels[12], [33, 207, 26, 255]);
+        assert_eq!(pixels[15], [126, 159, 139, 255]);
+        assert_eq!(pixels[2 * 4 + 1], [96, 144, 90, 255]);
+    }
+
+    #[test]
+    fn eac_values() {
+        // Base 128, multiplier 3, modifier table 13, index k % 8 for pixel k
+        let mut bits: u64 = (128 << 56) | (3 << 52) | (13 << 48);
+        for k in 0..16 {
+            bits |= (k % 8) << (45 - 3 * k);
+        }
+        let values = decode_eac(&bits.to_be_bytes());
+
+        let expected = [125, 122, 119, 98, 128, 131, 134, 155];
+        for k in 0..16 {
+            // Pixels are numbered column by column
+            let (x, y) = (k / 4, k % 4);
+            assert_eq!(values[y * 4 + x], expected[k % 8], "pixel {}", k);
+        }
+    }
+
+    #[test]
+    fn eac_clamps() {
+        // Base 10, multiplier 2, modifier -15 of table 0
+        let mut bits: u64 = (10 << 56) | (2 << 52);
+        for k in 0..16 {
+            bits |= 3 << (45 - 3 * k);
+        }
+
+        assert_eq!(decode_eac(&bits.to_be_bytes()), [0; 16]);
+    }
+}
@@ -0,0 +1,10 @@
+use cgmath::{Matrix4, Vector3};
+use rust3d_derive::Std140;
+
+/// Per-frame camera data, mirrors the `Camera` uniform block in the shaders
+#[derive(Clone, Copy, Std140)]
+pub struct Camera {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub position: Vector3<f32>,
+}
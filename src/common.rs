@@ -1,3 +1,38 @@
+use gl::types::*;
+
+use std::ffi::CStr;
+
 pub fn print_success_log(log: &str) {
     println!("OK: {}", &log);
 }
+
+pub fn print_warning_log(log: &str) {
+    println!("WARNING: {}", &log);
+}
+
+/// Whether the current context is at least OpenGL `version` or has `extension`
+pub fn gl_supports(version: (i32, i32), extension: &str) -> bool {
+    let mut major = 0;
+    let mut minor = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+    if (major, minor) >= version {
+        return true;
+    }
+
+    return gl_has_extension(extension);
+}
+
+/// Whether the current context has `extension`
+pub fn gl_has_extension(extension: &str) -> bool {
+    let mut extension_num = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_num);
+    }
+    return (0..extension_num as u32).any(|index| unsafe {
+        let name = gl::GetStringi(gl::EXTENSIONS, index);
+        !name.is_null() && CStr::from_ptr(name as *const GLchar).to_bytes() == extension.as_bytes()
+    });
+}
@@ -0,0 +1,499 @@
+use gl::types::*;
+use image::{DynamicImage, ImageBuffer, Luma, Pixel, Rgb, Rgba};
+
+use std::path::Path;
+
+use crate::block_decoder;
+use crate::common::{gl_has_extension, gl_supports};
+use crate::error::{Error, Result};
+use crate::resources;
+
+// From GL_EXT_texture_compression_s3tc and GL_EXT_texture_sRGB, which are not core
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1: GLenum = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: GLenum = 0x8C4F;
+
+const KTX2_MAGIC: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// Mipmap levels of a 2^31 texel wide image, the most a u32 size can have
+const MAX_LEVELS: usize = 32;
+
+/// Formats of 4x4 pixel blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// RGB, with 1-bit alpha if `alpha`
+    Bc1 {
+        alpha: bool,
+    },
+    /// RGBA with explicit 4-bit alpha
+    Bc2,
+    /// RGBA with interpolated alpha
+    Bc3,
+    /// Single channel
+    Bc4 {
+        signed: bool,
+    },
+    /// Two channels, e.g. the x and y of normal maps
+    Bc5 {
+        signed: bool,
+    },
+    /// HDR RGB
+    Bc6h {
+        signed: bool,
+    },
+    /// RGBA
+    Bc7,
+    Etc2Rgb,
+    /// RGB with 1-bit alpha
+    Etc2RgbA1,
+    Etc2Rgba,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        return match self {
+            BlockFormat::Bc1 { .. }
+            | BlockFormat::Bc4 { .. }
+            | BlockFormat::Etc2Rgb
+            | BlockFormat::Etc2RgbA1 => 8,
+            _ => 16,
+        };
+    }
+
+    /// Whether the format has an sRGB variant
+    pub fn has_srgb(self) -> bool {
+        return !matches!(
+            self,
+            BlockFormat::Bc4 { .. } | BlockFormat::Bc5 { .. } | BlockFormat::Bc6h { .. }
+        );
+    }
+
+    /// Internal format for `glCompressedTexImage2D`
+    pub fn gl_internal_format(self, srgb: bool) -> GLenum {
+        let srgb = srgb && self.has_srgb();
+        return match self {
+            BlockFormat::Bc1 { alpha: false } if srgb => COMPRESSED_SRGB_S3TC_DXT1,
+            BlockFormat::Bc1 { alpha: false } => COMPRESSED_RGB_S3TC_DXT1,
+            BlockFormat::Bc1 { alpha: true } if srgb => COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+            BlockFormat::Bc1 { alpha: true } => COMPRESSED_RGBA_S3TC_DXT1,
+            BlockFormat::Bc2 if srgb => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+            BlockFormat::Bc2 => COMPRESSED_RGBA_S3TC_DXT3,
+            BlockFormat::Bc3 if srgb => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+            BlockFormat::Bc3 => COMPRESSED_RGBA_S3TC_DXT5,
+            BlockFormat::Bc4 { signed: false } => gl::COMPRESSED_RED_RGTC1,
+            BlockFormat::Bc4 { signed: true } => gl::COMPRESSED_SIGNED_RED_RGTC1,
+            BlockFormat::Bc5 { signed: false } => gl::COMPRESSED_RG_RGTC2,
+            BlockFormat::Bc5 { signed: true } => gl::COMPRESSED_SIGNED_RG_RGTC2,
+            BlockFormat::Bc6h { signed: false } => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            BlockFormat::Bc6h { signed: true } => gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            BlockFormat::Bc7 if srgb => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            BlockFormat::Bc7 => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            BlockFormat::Etc2Rgb if srgb => gl::COMPRESSED_SRGB8_ETC2,
+            BlockFormat::Etc2Rgb => gl::COMPRESSED_RGB8_ETC2,
+            BlockFormat::Etc2RgbA1 if srgb => gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            BlockFormat::Etc2RgbA1 => gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            BlockFormat::Etc2Rgba if srgb => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            BlockFormat::Etc2Rgba => gl::COMPRESSED_RGBA8_ETC2_EAC,
+        };
+    }
+
+    /// Whether the driver can sample the format
+    pub fn is_supported(self, srgb: bool) -> bool {
+        return match self {
+            BlockFormat::Bc1 { .. } | BlockFormat::Bc2 | BlockFormat::Bc3 => {
+                gl_has_extension("GL_EXT_texture_compression_s3tc")
+                    && (!srgb
+                        || gl_has_extension("GL_EXT_texture_sRGB")
+                        || gl_has_extension("GL_EXT_texture_compression_s3tc_srgb"))
+            }
+            BlockFormat::Bc4 { .. } | BlockFormat::Bc5 { .. } => {
+                gl_supports((3, 0), "GL_ARB_texture_compression_rgtc")
+            }
+            BlockFormat::Bc6h { .. } | BlockFormat::Bc7 => {
+                gl_supports((4, 2), "GL_ARB_texture_compression_bptc")
+            }
+            BlockFormat::Etc2Rgb | BlockFormat::Etc2RgbA1 | BlockFormat::Etc2Rgba => {
+                gl_supports((4, 3), "GL_ARB_ES3_compatibility")
+            }
+        };
+    }
+
+    /// Source channel of red, green, blue and alpha when sampled.
+    /// A single channel is spread to gray, like gray images.
+    pub fn swizzle(self) -> [GLenum; 4] {
+        return match self {
+            BlockFormat::Bc4 { .. } => [gl::RED, gl::RED, gl::RED, gl::ONE],
+            _ => [gl::RED, gl::GREEN, gl::BLUE, gl::ALPHA],
+        };
+    }
+}
+
+/// Block-compressed texture read from a KTX2 or DDS file
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    /// Whether the file stores sRGB colors, or `None` if it does not say
+    pub srgb: Option<bool>,
+    pub width: u32,
+    pub height: u32,
+    /// Blocks of each mipmap level, from the base level down
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// Whether the file is read by `CompressedImage::read`, judging by its extension
+pub fn is_compressed_file(path: &Path) -> bool {
+    return match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => {
+            extension.eq_ignore_ascii_case("ktx2") || extension.eq_ignore_ascii_case("dds")
+        }
+        None => false,
+    };
+}
+
+impl CompressedImage {
+    /// Read a KTX2 or DDS file. Only 2D textures without supercompression are read.
+    pub fn read(path: &Path) -> Result<CompressedImage> {
+        let data = resources::read(path)?;
+        let image = if data.starts_with(KTX2_MAGIC) {
+            CompressedImage::parse_ktx2(&data)
+        } else if data.starts_with(DDS_MAGIC) {
+            CompressedImage::parse_dds(&data)
+        } else {
+            Err("not a KTX2 or DDS file".to_string())
+        };
+
+        return image.map_err(|message| Error::Decode {
+            path: path.to_string_lossy().into_owned(),
+            message,
+        });
+    }
+
+    fn parse_ktx2(data: &[u8]) -> std::result::Result<CompressedImage, String> {
+        let reader = ktx2::Reader::new(data).map_err(|e| e.to_string())?;
+        let header = reader.header();
+
+        if header.supercompression_scheme.is_some() {
+            return Err("supercompressed KTX2 is not supported".to_string());
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            return Err("only 2D textures are supported".to_string());
+        }
+
+        let (format, srgb) = match header.format {
+            Some(ktx2::Format::BC1_RGB_UNORM_BLOCK) => (BlockFormat::Bc1 { alpha: false }, false),
+            Some(ktx2::Format::BC1_RGB_SRGB_BLOCK) => (BlockFormat::Bc1 { alpha: false }, true),
+            Some(ktx2::Format::BC1_RGBA_UNORM_BLOCK) => (BlockFormat::Bc1 { alpha: true }, false),
+            Some(ktx2::Format::BC1_RGBA_SRGB_BLOCK) => (BlockFormat::Bc1 { alpha: true }, true),
+            Some(ktx2::Format::BC2_UNORM_BLOCK) => (BlockFormat::Bc2, false),
+            Some(ktx2::Format::BC2_SRGB_BLOCK) => (BlockFormat::Bc2, true),
+            Some(ktx2::Format::BC3_UNORM_BLOCK) => (BlockFormat::Bc3, false),
+            Some(ktx2::Format::BC3_SRGB_BLOCK) => (BlockFormat::Bc3, true),
+            Some(ktx2::Format::BC4_UNORM_BLOCK) => (BlockFormat::Bc4 { signed: false }, false),
+            Some(ktx2::Format::BC4_SNORM_BLOCK) => (BlockFormat::Bc4 { signed: true }, false),
+            Some(ktx2::Format::BC5_UNORM_BLOCK) => (BlockFormat::Bc5 { signed: false }, false),
+            Some(ktx2::Format::BC5_SNORM_BLOCK) => (BlockFormat::Bc5 { signed: true }, false),
+            Some(ktx2::Format::BC6H_UFLOAT_BLOCK) => (BlockFormat::Bc6h { signed: false }, false),
+            Some(ktx2::Format::BC6H_SFLOAT_BLOCK) => (BlockFormat::Bc6h { signed: true }, false),
+            Some(ktx2::Format::BC7_UNORM_BLOCK) => (BlockFormat::Bc7, false),
+            Some(ktx2::Format::BC7_SRGB_BLOCK) => (BlockFormat::Bc7, true),
+            Some(ktx2::Format::ETC2_R8G8B8_UNORM_BLOCK) => (BlockFormat::Etc2Rgb, false),
+            Some(ktx2::Format::ETC2_R8G8B8_SRGB_BLOCK) => (BlockFormat::Etc2Rgb, true),
+            Some(ktx2::Format::ETC2_R8G8B8A1_UNORM_BLOCK) => (BlockFormat::Etc2RgbA1, false),
+            Some(ktx2::Format::ETC2_R8G8B8A1_SRGB_BLOCK) => (BlockFormat::Etc2RgbA1, true),
+            Some(ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK) => (BlockFormat::Etc2Rgba, false),
+            Some(ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK) => (BlockFormat::Etc2Rgba, true),
+            Some(format) => return Err(format!("{:?} is not a BCn or ETC2 format", format)),
+            None => return Err("Basis Universal KTX2 is not supported".to_string()),
+        };
+
+        let levels = reader.levels().map(|level| level.data.to_vec()).collect();
+
+        return CompressedImage::new(
+            format,
+            Some(srgb),
+            header.pixel_width,
+            header.pixel_height.max(1),
+            levels,
+        );
+    }
+
+    fn parse_dds(data: &[u8]) -> std::result::Result<CompressedImage, String> {
+        use ddsfile::{Caps2, Dds, DxgiFormat, FourCC};
+
+        let dds = Dds::read(data).map_err(|e| e.to_string())?;
+        if dds.get_depth() > 1
+            || dds.get_num_array_layers() > 1
+            || dds.header.caps2.contains(Caps2::CUBEMAP)
+        {
+            return Err("only 2D textures are supported".to_string());
+        }
+
+        let (format, srgb) = match &dds.header10 {
+            Some(header10) => match header10.dxgi_format {
+                DxgiFormat::BC1_Typeless => (BlockFormat::Bc1 { alpha: true }, None),
+                DxgiFormat::BC1_UNorm => (BlockFormat::Bc1 { alpha: true }, Some(false)),
+                DxgiFormat::BC1_UNorm_sRGB => (BlockFormat::Bc1 { alpha: true }, Some(true)),
+                DxgiFormat::BC2_Typeless => (BlockFormat::Bc2, None),
+                DxgiFormat::BC2_UNorm => (BlockFormat::Bc2, Some(false)),
+                DxgiFormat::BC2_UNorm_sRGB => (BlockFormat::Bc2, Some(true)),
+                DxgiFormat::BC3_Typeless => (BlockFormat::Bc3, None),
+                DxgiFormat::BC3_UNorm => (BlockFormat::Bc3, Some(false)),
+                DxgiFormat::BC3_UNorm_sRGB => (BlockFormat::Bc3, Some(true)),
+                DxgiFormat::BC4_Typeless | DxgiFormat::BC4_UNorm => {
+                    (BlockFormat::Bc4 { signed: false }, None)
+                }
+                DxgiFormat::BC4_SNorm => (BlockFormat::Bc4 { signed: true }, None),
+                DxgiFormat::BC5_Typeless | DxgiFormat::BC5_UNorm => {
+                    (BlockFormat::Bc5 { signed: false }, None)
+                }
+                DxgiFormat::BC5_SNorm => (BlockFormat::Bc5 { signed: true }, None),
+                DxgiFormat::BC6H_Typeless | DxgiFormat::BC6H_UF16 => {
+                    (BlockFormat::Bc6h { signed: false }, None)
+                }
+                DxgiFormat::BC6H_SF16 => (BlockFormat::Bc6h { signed: true }, None),
+                DxgiFormat::BC7_Typeless => (BlockFormat::Bc7, None),
+                DxgiFormat::BC7_UNorm => (BlockFormat::Bc7, Some(false)),
+                DxgiFormat::BC7_UNorm_sRGB => (BlockFormat::Bc7, Some(true)),
+                format => return Err(format!("{:?} is not a BCn format", format)),
+            },
+            // Files without the DX10 header name the format with a FourCC and say
+            // nothing about the color space
+            None => match dds.header.spf.fourcc.as_ref().map(|fourcc| fourcc.0) {
+                Some(FourCC::DXT1) => (BlockFormat::Bc1 { alpha: true }, None),
+                Some(FourCC::DXT2) | Some(FourCC::DXT3) => (BlockFormat::Bc2, None),
+                Some(FourCC::DXT4) | Some(FourCC::DXT5) => (BlockFormat::Bc3, None),
+                Some(FourCC::ATI1) | Some(FourCC::BC4_UNORM) => {
+                    (BlockFormat::Bc4 { signed: false }, None)
+                }
+                Some(FourCC::BC4_SNORM) => (BlockFormat::Bc4 { signed: true }, None),
+                Some(FourCC::ATI2) => (BlockFormat::Bc5 { signed: false }, None),
+                Some(FourCC::BC5_SNORM) => (BlockFormat::Bc5 { signed: true }, None),
+                _ => return Err("not a BCn format".to_string()),
+            },
+        };
+
+        // Levels are stored one after another, from the base level down
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let mut levels = Vec::new();
+        let mut offset = 0;
+        for level in 0..dds.get_num_mipmap_levels() {
+            let size = level_bytes(format, level_size(width, height, level as usize));
+            match dds.data.get(offset..offset + size) {
+                Some(data) => levels.push(data.to_vec()),
+                None => break,
+            }
+            offset += size;
+        }
+
+        return CompressedImage::new(format, srgb, width, height, levels);
+    }
+
+    /// Check that every level holds all of its blocks
+    fn new(
+        format: BlockFormat,
+        srgb: Option<bool>,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+    ) -> std::result::Result<CompressedImage, String> {
+        if width == 0 || height == 0 || levels.is_empty() {
+            return Err("image is empty".to_string());
+        }
+        if levels.len() > MAX_LEVELS {
+            return Err(format!(
+                "image has {} levels, more than the {} of the largest 32-bit size",
+                levels.len(),
+                MAX_LEVELS
+            ));
+        }
+        for (level, data) in levels.iter().enumerate() {
+            let expected = level_bytes(format, level_size(width, height, level));
+            if data.len() < expected {
+                return Err(format!(
+                    "level {} has {} bytes instead of {}",
+                    level,
+                    data.len(),
+                    expected
+                ));
+            }
+        }
+
+        return Ok(CompressedImage {
+            format,
+            srgb,
+            width,
+            height,
+            levels,
+        });
+    }
+
+    /// Width and height of a mipmap level
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        return level_size(self.width, self.height, level);
+    }
+
+    /// Blocks of a level, without any padding the file had after them
+    pub fn level_data(&self, level: usize) -> &[u8] {
+        let size = level_bytes(self.format, self.level_size(level));
+        return &self.levels[level][..size];
+    }
+
+    /// Decode a level on the CPU. Unsigned formats become 8-bit images and
+    /// signed ones become float images. BC6H is not decoded.
+    pub fn decompress(&self, level: usize) -> Result<DynamicImage> {
+        let image = match self.format {
+            BlockFormat::Bc1 { alpha } => {
+                DynamicImage::ImageRgba8(self.decode_level(level, |block| {
+                    block_decoder::decode_bc1(block, alpha).map(Rgba)
+                }))
+            }
+            BlockFormat::Bc2 => DynamicImage::ImageRgba8(
+                self.decode_level(level, |block| block_decoder::decode_bc2(block).map(Rgba)),
+            ),
+            BlockFormat::Bc3 => DynamicImage::ImageRgba8(
+                self.decode_level(level, |block| block_decoder::decode_bc3(block).map(Rgba)),
+            ),
+            BlockFormat::Bc4 { signed: false } => {
+                DynamicImage::ImageLuma8(self.decode_level(level, |block| {
+                    block_decoder::decode_bc4_unorm(block).map(|value| Luma([value]))
+                }))
+            }
+            BlockFormat::Bc4 { signed: true } => {
+                DynamicImage::ImageRgb32F(self.decode_level(level, |block| {
+                    block_decoder::decode_bc4_snorm(block).map(|value| Rgb([value; 3]))
+                }))
+            }
+            BlockFormat::Bc5 { signed: false } => {
+                DynamicImage::ImageRgb8(self.decode_level(level, |block| {
+                    let red = block_decoder::decode_bc4_unorm(&block[0..8]);
+                    let green = block_decoder::decode_bc4_unorm(&block[8..16]);
+                    let mut pixels = [Rgb([0; 3]); 16];
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        *pixel = Rgb([red[i], green[i], 0]);
+                    }
+                    pixels
+                }))
+            }
+            BlockFormat::Bc5 { signed: true } => {
+                DynamicImage::ImageRgb32F(self.decode_level(level, |block| {
+                    let red = block_decoder::decode_bc4_snorm(&block[0..8]);
+                    let green = block_decoder::decode_bc4_snorm(&block[8..16]);
+                    let mut pixels = [Rgb([0.0; 3]); 16];
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        *pixel = Rgb([red[i], green[i], 0.0]);
+                    }
+                    pixels
+                }))
+            }
+            BlockFormat::Bc6h { .. } => {
+                return Err(Error::Unsupported {
+                    feature: "BC6H textures",
+                    requirement: "OpenGL 4.2 or GL_ARB_texture_compression_bptc",
+                })
+            }
+            BlockFormat::Bc7 => DynamicImage::ImageRgba8(
+                self.decode_level(level, |block| block_decoder::decode_bc7(block).map(Rgba)),
+            ),
+            BlockFormat::Etc2Rgb | BlockFormat::Etc2RgbA1 => {
+                let punchthrough = self.format == BlockFormat::Etc2RgbA1;
+                DynamicImage::ImageRgba8(self.decode_level(level, |block| {
+                    block_decoder::decode_etc2_rgb(block, punchthrough).map(Rgba)
+                }))
+            }
+            BlockFormat::Etc2Rgba => DynamicImage::ImageRgba8(self.decode_level(level, |block| {
+                block_decoder::decode_etc2_rgba(block).map(Rgba)
+            })),
+        };
+
+        return Ok(image);
+    }
+
+    /// Image from the decoded blocks of a level, cropping partial blocks at the edges
+    fn decode_level<P: Pixel>(
+        &self,
+        level: usize,
+        decode: impl Fn(&[u8]) -> [P; 16],
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let (width, height) = self.level_size(level);
+        let mut image = ImageBuffer::new(width, height);
+        let columns = width.div_ceil(4);
+
+        for (i, block) in self
+            .level_data(level)
+            .chunks_exact(self.format.block_bytes())
+            .enumerate()
+        {
+            let (block_x, block_y) = (i as u32 % columns * 4, i as u32 / columns * 4);
+            for (j, pixel) in decode(block).iter().enumerate() {
+                let (x, y) = (block_x + j as u32 % 4, block_y + j as u32 / 4);
+                if x < width && y < height {
+                    image.put_pixel(x, y, *pixel);
+                }
+            }
+        }
+
+        return image;
+    }
+}
+
+fn level_size(width: u32, height: u32, level: usize) -> (u32, u32) {
+    let halve = |size: u32| size.checked_shr(level as u32).unwrap_or(0).max(1);
+    return (halve(width), halve(height));
+}
+
+/// Bytes of the blocks covering a level, counting partial blocks at the edges
+fn level_bytes(format: BlockFormat, (width, height): (u32, u32)) -> usize {
+    let blocks = (width as usize).div_ceil(4) * (height as usize).div_ceil(4);
+    return blocks * format.block_bytes();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_decoder::tests::bc4_block;
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        // 5x3 is covered by two blocks side by side
+        let data = [bc4_block(255, 0), bc4_block(0, 255)].concat();
+        let image =
+            CompressedImage::new(BlockFormat::Bc4 { signed: false }, None, 5, 3, vec![data])
+                .unwrap();
+        let image = image.decompress(0).unwrap().to_luma8();
+
+        let palettes = [
+            [255, 0, 219, 182, 146, 109, 73, 36],
+            [0, 255, 51, 102, 153, 204, 0, 255],
+        ];
+        assert_eq!(image.dimensions(), (5, 3));
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let index = (y * 4 + x % 4) as usize % 8;
+            assert_eq!(
+                pixel[0],
+                palettes[x as usize / 4][index],
+                "pixel {}, {}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn levels_are_limited_by_the_size() {
+        assert_eq!(level_size(5, 3, 1), (2, 1));
+        assert_eq!(level_size(u32::MAX, 1, 31), (1, 1));
+        assert_eq!(level_size(u32::MAX, 1, 40), (1, 1));
+
+        let block = vec![0; 8];
+        let format = BlockFormat::Bc1 { alpha: false };
+        let image =
+            |level_num| CompressedImage::new(format, None, 1, 1, vec![block.clone(); level_num]);
+        assert!(image(MAX_LEVELS).is_ok());
+        assert!(image(MAX_LEVELS + 1).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use gl::types::*;
+
+use std::ops::Deref;
+
+use crate::common::gl_supports;
+use crate::error::{Error, Result};
+use crate::shader::Shader;
+
+/// Program with a single compute stage. Uniform setters are available through `Shader`.
+pub struct ComputeShader {
+    shader: Shader,
+    /// `local_size_x/y/z` declared in the shader
+    work_group_size: [i32; 3],
+}
+
+impl ComputeShader {
+    pub fn new(path: &str) -> Result<ComputeShader> {
+        return ComputeShader::with_defines(path, &[]);
+    }
+
+    pub fn with_defines(path: &str, defines: &[(&str, &str)]) -> Result<ComputeShader> {
+        if !supports_compute() {
+            return Err(Error::Unsupported {
+                feature: "Compute shader",
+                requirement: "OpenGL 4.3 or GL_ARB_compute_shader",
+            });
+        }
+
+        let shader = Shader::builder().compute(path).defines(defines).build()?;
+        let work_group_size = unsafe { query_work_group_size(&shader) };
+
+        return Ok(ComputeShader {
+            shader,
+            work_group_size,
+        });
+    }
+
+    /// Same as `Shader::reload_if_modified`
+    pub fn reload_if_modified(&mut self) -> Result<bool> {
+        let reloaded = self.shader.reload_if_modified()?;
+        if reloaded {
+            self.work_group_size = unsafe { query_work_group_size(&self.shader) };
+        }
+
+        return Ok(reloaded);
+    }
+
+    /// Bind the program and run `x * y * z` work groups
+    pub unsafe fn dispatch(&self, x: u32, y: u32, z: u32) {
+        self.shader.use_program();
+        gl::DispatchCompute(x, y, z);
+    }
+
+    /// Run enough work groups to cover `x * y * z` invocations.
+    /// The shader should skip invocations outside the size.
+    pub unsafe fn dispatch_size(&self, x: u32, y: u32, z: u32) {
+        let [size_x, size_y, size_z] = self.work_group_size.map(|size| size.max(1) as u32);
+        self.dispatch(x.div_ceil(size_x), y.div_ceil(size_y), z.div_ceil(size_z));
+    }
+}
+
+impl Deref for ComputeShader {
+    type Target = Shader;
+
+    fn deref(&self) -> &Shader {
+        return &self.shader;
+    }
+}
+
+/// Make writes of earlier dispatches visible to later accesses of the kinds in `barriers`
+/// (`gl::SHADER_STORAGE_BARRIER_BIT`, `gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT`, ...)
+pub unsafe fn memory_barrier(barriers: GLbitfield) {
+    gl::MemoryBarrier(barriers);
+}
+
+/// Whether the current context can run compute shaders
+pub fn supports_compute() -> bool {
+    return gl::DispatchCompute::is_loaded() && gl_supports((4, 3), "GL_ARB_compute_shader");
+}
+
+unsafe fn query_work_group_size(shader: &Shader) -> [i32; 3] {
+    let mut size = [0; 3];
+    gl::GetProgramiv(shader.id(), gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+
+    return size;
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    /// File could not be read
+    Io { path: String, source: io::Error },
+    /// Text asset (OBJ, MTL, ...) is malformed at `line`
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    /// Binary asset (image, glTF, ...) could not be decoded
+    Decode { path: String, message: String },
+    /// Shader stage failed to compile. `log` is the driver's info log.
+    Compile {
+        stage: &'static str,
+        path: String,
+        log: String,
+    },
+    /// Program failed to link. `log` is the driver's info log.
+    Link { program: String, log: String },
+    /// The GL context lacks a version or extension the feature needs
+    Unsupported {
+        feature: &'static str,
+        requirement: &'static str,
+    },
+    /// No resource is registered with the id
+    UnknownResource { kind: &'static str, id: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Error {
+        return Error::Io {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        };
+    }
+
+    /// Fill in the file path of an error raised while parsing source without a path
+    pub fn with_path(self, path: &str) -> Error {
+        return match self {
+            Error::Parse { line, message, .. } => Error::Parse {
+                path: path.to_string(),
+                line,
+                message,
+            },
+            error => error,
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "Failed to read {}: {}", path, source),
+            Error::Parse {
+                path,
+                line,
+                message,
+            } if path.is_empty() => {
+                write!(f, "line {}: {}", line, message)
+            }
+            Error::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path, line, message),
+            Error::Decode { path, message } => write!(f, "Failed to decode {}: {}", path, message),
+            Error::Compile { stage, path, log } => {
+                write!(f, "Failed to compile {} shader {}:\n{}", stage, path, log)
+            }
+            Error::Link { program, log } => {
+                write!(f, "Failed to link program {}:\n{}", program, log)
+            }
+            Error::Unsupported {
+                feature,
+                requirement,
+            } => write!(f, "{} needs {}", feature, requirement),
+            Error::UnknownResource { kind, id } => write!(f, "Unknown {}: {}", kind, id),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        };
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// GL objects that are alive, with a label telling where they came from.
+/// Only tracked in debug builds.
+static LIVE_OBJECTS: Mutex<BTreeMap<(&'static str, u32), String>> = Mutex::new(BTreeMap::new());
+
+fn track(kind: &'static str, id: u32, label: &str) {
+    if cfg!(debug_assertions) {
+        if let Ok(mut objects) = LIVE_OBJECTS.lock() {
+            objects.insert((kind, id), label.to_string());
+        }
+    }
+}
+
+fn untrack(kind: &'static str, id: u32) {
+    if cfg!(debug_assertions) {
+        if let Ok(mut objects) = LIVE_OBJECTS.lock() {
+            objects.remove(&(kind, id));
+        }
+    }
+}
+
+/// Print GL objects that have not been deleted yet (debug builds only).
+/// Call this at shutdown after all owners have been dropped.
+pub fn report_leaks() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let objects = match LIVE_OBJECTS.lock() {
+        Ok(objects) => objects,
+        Err(_) => return,
+    };
+
+    if objects.is_empty() {
+        println!("OK: No GL object leaked");
+        return;
+    }
+
+    for ((kind, id), label) in objects.iter() {
+        println!("LEAK: {} {} ({})", kind, id, label);
+    }
+}
+
+macro_rules! gl_object {
+    ($(#[$meta:meta])* $name:ident, $create:block, |$id:ident| $delete:block) => {
+        $(#[$meta])*
+        pub struct $name {
+            id: u32,
+        }
+
+        impl $name {
+            pub fn new(label: &str) -> $name {
+                // Use unsafe block to use OpenGL functions
+                let id = unsafe { $create };
+
+                track(stringify!($name), id, label);
+
+                return $name { id };
+            }
+
+            pub fn id(&self) -> u32 {
+                return self.id;
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                let $id = self.id;
+
+                // Use unsafe block to use OpenGL functions
+                unsafe { $delete }
+
+                untrack(stringify!($name), $id);
+            }
+        }
+    };
+}
+
+gl_object!(
+    /// Owned buffer object
+    Buffer,
+    {
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        id
+    },
+    |id| { gl::DeleteBuffers(1, &id); }
+);
+
+gl_object!(
+    /// Owned vertex array object
+    VertexArray,
+    {
+        let mut id = 0;
+        gl::GenVertexArrays(1, &mut id);
+        id
+    },
+    |id| { gl::DeleteVertexArrays(1, &id); }
+);
+
+gl_object!(
+    /// Owned program object
+    Program,
+    { gl::CreateProgram() },
+    |id| { gl::DeleteProgram(id); }
+);
+
+gl_object!(
+    /// Owned texture object
+    Texture,
+    {
+        let mut id = 0;
+        gl::GenTextures(1, &mut id);
+        id
+    },
+    |id| { gl::DeleteTextures(1, &id); }
+);
+
+gl_object!(
+    /// Owned sampler object
+    Sampler,
+    {
+        let mut id = 0;
+        gl::GenSamplers(1, &mut id);
+        id
+    },
+    |id| { gl::DeleteSamplers(1, &id); }
+);
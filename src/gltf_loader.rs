@@ -0,0 +1,419 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use gl::types::GLenum;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use image::{DynamicImage, ImageBuffer};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::material::Material;
+use crate::mesh::{Mesh, Submesh};
+use crate::obj_loader::face_normal;
+use crate::resources;
+use crate::sampler::{Filter, SamplerDesc, Wrap};
+use crate::texture_loader::{TextureDesc, TextureLoader};
+use crate::vertex::{BasicVertex, Vertex};
+
+/// One glTF primitive, placed in the scene by its node's world transform
+pub struct GltfPrimitive {
+    pub name: String,
+    pub transform: Matrix4<f32>,
+    /// Primitive topology (gl::TRIANGLES, gl::LINES, ...)
+    pub mode: GLenum,
+    pub vertices: Vec<BasicVertex>,
+    pub indices: Vec<u32>,
+    pub material: Material,
+}
+
+impl GltfPrimitive {
+    pub fn to_vertex(&self, usage: GLenum) -> Vertex {
+        let mut vertex = Vertex::new(&self.vertices, usage);
+        vertex.set_indices_compact(&self.indices, usage);
+        vertex.set_mode(self.mode);
+
+        return vertex;
+    }
+
+    pub fn to_mesh(&self, usage: GLenum) -> Mesh {
+        return Mesh {
+            vertex: self.to_vertex(usage),
+            submeshes: vec![Submesh {
+                name: self.name.clone(),
+                first: 0,
+                count: self.indices.len() as i32,
+                material: self.material.clone(),
+            }],
+            transform: self.transform,
+        };
+    }
+}
+
+pub struct GltfScene {
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+impl GltfScene {
+    /// Import `.gltf` or `.glb` file.
+    /// Textures are registered in the texture loader as `<path>#texture<index>`.
+    pub fn load(path: &Path, texture_loader: &mut TextureLoader) -> Result<GltfScene> {
+        let path_str = path.to_string_lossy();
+        let decode_error = |message: String| Error::Decode {
+            path: path_str.to_string(),
+            message,
+        };
+
+        // Buffers and images are read relative to the file, so it must be on disk
+        let file = resources::find(path)?;
+        let (document, buffers, images) = match gltf::import(&file) {
+            Ok(import) => import,
+            Err(gltf::Error::Io(e)) => return Err(Error::io(path, e)),
+            Err(e) => return Err(decode_error(e.to_string())),
+        };
+
+        let mut dynamic_images = Vec::with_capacity(images.len());
+        for (index, data) in images.into_iter().enumerate() {
+            let image = to_dynamic_image(data)
+                .ok_or_else(|| decode_error(format!("image {} does not match its size", index)))?;
+            dynamic_images.push(image);
+        }
+
+        // Register each texture used by a material with its sampler. Base color is loaded
+        // as linear like the OBJ textures, since the framebuffer does not convert to sRGB.
+        let mut texture_ids = HashMap::new();
+        for gltf_material in document.materials() {
+            let textures = [
+                gltf_material
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .map(|info| info.texture()),
+                gltf_material.normal_texture().map(|info| info.texture()),
+            ];
+            for texture in textures {
+                let texture = match texture {
+                    Some(texture) if !texture_ids.contains_key(&texture.index()) => texture,
+                    _ => continue,
+                };
+
+                let id = format!("{}#texture{}", path_str, texture.index());
+                let sampler = to_sampler_desc(&texture.sampler());
+                let desc = TextureDesc {
+                    sampler,
+                    mipmaps: sampler.mipmap_filter.is_some(),
+                    ..TextureDesc::default()
+                };
+                let image = dynamic_images[texture.source().index()].clone();
+                texture_loader.load_image(image, &id, &desc)?;
+                texture_ids.insert(texture.index(), id);
+            }
+        }
+
+        let scene = match document.default_scene() {
+            Some(scene) => scene,
+            None => match document.scenes().next() {
+                Some(scene) => scene,
+                None => return Err(decode_error(String::from("file has no scene"))),
+            },
+        };
+
+        let mut primitives = Vec::new();
+        for node in scene.nodes() {
+            load_node(
+                &node,
+                Matrix4::identity(),
+                &buffers,
+                &texture_ids,
+                &mut primitives,
+            )
+            .map_err(decode_error)?;
+        }
+
+        return Ok(GltfScene { primitives });
+    }
+}
+
+fn load_node(
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    texture_ids: &HashMap<usize, String>,
+    primitives: &mut Vec<GltfPrimitive>,
+) -> std::result::Result<(), String> {
+    // TRS and matrix transforms are both returned as a column-major matrix
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let mesh_name = mesh
+            .name()
+            .or(node.name())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("mesh{}", mesh.index()));
+
+        for primitive in mesh.primitives() {
+            let (vertices, indices) = read_primitive(&primitive, buffers, &mesh_name)?;
+
+            let gltf_material = primitive.material();
+            let pbr = gltf_material.pbr_metallic_roughness();
+            let base_color = pbr.base_color_factor();
+
+            let mut material = Material::new(gltf_material.name().unwrap_or(&mesh_name));
+            material.diffuse = Vector3::new(base_color[0], base_color[1], base_color[2]);
+            material.alpha = base_color[3];
+            // Reflectivity stays 0. metallicFactor defaults to 1, which would turn every
+            // material without one into a mirror of the environment map.
+            material.diffuse_texture = pbr
+                .base_color_texture()
+                .map(|info| texture_ids[&info.texture().index()].clone());
+            material.normal_texture = gltf_material
+                .normal_texture()
+                .map(|info| texture_ids[&info.texture().index()].clone());
+
+            primitives.push(GltfPrimitive {
+                name: format!("{}[{}]", mesh_name, primitive.index()),
+                transform,
+                mode: to_gl_mode(primitive.mode()),
+                vertices,
+                indices,
+                material,
+            });
+        }
+    }
+
+    for child in node.children() {
+        load_node(&child, transform, buffers, texture_ids, primitives)?;
+    }
+
+    return Ok(());
+}
+
+/// Read primitive into vertices and indices. Errors are returned as a message.
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    mesh_name: &str,
+) -> std::result::Result<(Vec<BasicVertex>, Vec<u32>), String> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = match reader.read_positions() {
+        Some(positions) => positions.collect(),
+        None => {
+            return Err(format!(
+                "primitive of mesh '{}' has no POSITION attribute",
+                mesh_name
+            ))
+        }
+    };
+    // Integer tex coords are normalized to [0, 1] by `into_f32`
+    let tex_coords: Option<Vec<[f32; 2]>> =
+        reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+    if let Some(tex_coords) = &tex_coords {
+        check_count("TEXCOORD_0", tex_coords.len(), positions.len(), mesh_name)?;
+    }
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    // Indices go to the element buffer as they are, so they must all be in range
+    if let Some(index) = indices
+        .iter()
+        .find(|index| **index as usize >= positions.len())
+    {
+        return Err(format!(
+            "primitive of mesh '{}' has index {}, but only {} vertices",
+            mesh_name,
+            index,
+            positions.len()
+        ));
+    }
+    let normals: Vec<[f32; 3]> = match reader.read_normals() {
+        Some(normals) => normals.collect(),
+        None => smooth_normals(&positions, &triangulate(primitive.mode(), &indices)),
+    };
+    check_count("NORMAL", normals.len(), positions.len(), mesh_name)?;
+
+    let mut vertices = Vec::with_capacity(positions.len());
+    for (i, &position) in positions.iter().enumerate() {
+        // glTF puts the tex coord origin at the top left, OpenGL at the bottom left
+        let tex_coord = match &tex_coords {
+            Some(tex_coords) => [tex_coords[i][0], 1.0 - tex_coords[i][1]],
+            None => [0.0, 0.0],
+        };
+
+        vertices.push(BasicVertex {
+            position,
+            normal: normals[i],
+            tex_coord,
+        });
+    }
+
+    return Ok((vertices, indices));
+}
+
+/// Every attribute of a primitive must have one value per vertex
+fn check_count(
+    attribute: &str,
+    count: usize,
+    vertex_num: usize,
+    mesh_name: &str,
+) -> std::result::Result<(), String> {
+    if count != vertex_num {
+        return Err(format!(
+            "primitive of mesh '{}' has {} {} values for {} vertices",
+            mesh_name, count, attribute, vertex_num
+        ));
+    }
+
+    return Ok(());
+}
+
+fn to_gl_mode(mode: Mode) -> GLenum {
+    return match mode {
+        Mode::Points => gl::POINTS,
+        Mode::Lines => gl::LINES,
+        Mode::LineLoop => gl::LINE_LOOP,
+        Mode::LineStrip => gl::LINE_STRIP,
+        Mode::Triangles => gl::TRIANGLES,
+        Mode::TriangleStrip => gl::TRIANGLE_STRIP,
+        Mode::TriangleFan => gl::TRIANGLE_FAN,
+    };
+}
+
+/// Average face normals of the triangles sharing each vertex
+fn smooth_normals(positions: &[[f32; 3]], triangles: &[u32]) -> Vec<[f32; 3]> {
+    let mut sums = vec![Vector3::new(0.0f32, 0.0, 0.0); positions.len()];
+
+    for triangle in triangles.chunks_exact(3) {
+        let p = [
+            positions[triangle[0] as usize],
+            positions[triangle[1] as usize],
+            positions[triangle[2] as usize],
+        ];
+        let normal = Vector3::from(face_normal(&p));
+        for &index in triangle {
+            sums[index as usize] += normal;
+        }
+    }
+
+    return sums
+        .into_iter()
+        .map(|sum| {
+            if sum.magnitude2() == 0.0 {
+                [0.0, 0.0, 0.0]
+            } else {
+                sum.normalize().into()
+            }
+        })
+        .collect();
+}
+
+/// Convert indices of any triangle topology into a triangle list.
+/// Points and lines have no triangles.
+fn triangulate(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    let mut triangles = Vec::new();
+
+    match mode {
+        Mode::Triangles => triangles.extend_from_slice(&indices[..indices.len() / 3 * 3]),
+        Mode::TriangleStrip => {
+            for i in 2..indices.len() {
+                // Keep winding order consistent on every other triangle
+                if i % 2 == 0 {
+                    triangles.extend_from_slice(&[indices[i - 2], indices[i - 1], indices[i]]);
+                } else {
+                    triangles.extend_from_slice(&[indices[i - 1], indices[i - 2], indices[i]]);
+                }
+            }
+        }
+        Mode::TriangleFan => {
+            for i in 2..indices.len() {
+                triangles.extend_from_slice(&[indices[0], indices[i - 1], indices[i]]);
+            }
+        }
+        _ => {}
+    }
+
+    return triangles;
+}
+
+/// Sampler of a glTF texture. Filters the file leaves to the viewer use the defaults.
+fn to_sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let default = SamplerDesc::default();
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (Filter::Nearest, None),
+        Some(MinFilter::Linear) => (Filter::Linear, None),
+        Some(MinFilter::NearestMipmapNearest) => (Filter::Nearest, Some(Filter::Nearest)),
+        Some(MinFilter::LinearMipmapNearest) => (Filter::Linear, Some(Filter::Nearest)),
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Some(Filter::Linear)),
+        Some(MinFilter::LinearMipmapLinear) => (Filter::Linear, Some(Filter::Linear)),
+        None => (default.min_filter, default.mipmap_filter),
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        Some(MagFilter::Linear) => Filter::Linear,
+        None => default.mag_filter,
+    };
+
+    return SamplerDesc {
+        wrap_s: to_wrap(sampler.wrap_s()),
+        wrap_t: to_wrap(sampler.wrap_t()),
+        min_filter,
+        mag_filter,
+        mipmap_filter,
+        ..default
+    };
+}
+
+fn to_wrap(mode: WrappingMode) -> Wrap {
+    return match mode {
+        WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::Repeat => Wrap::Repeat,
+    };
+}
+
+fn to_dynamic_image(data: gltf::image::Data) -> Option<DynamicImage> {
+    let (width, height) = (data.width, data.height);
+
+    return match data.format {
+        Format::R8 => {
+            ImageBuffer::from_raw(width, height, data.pixels).map(DynamicImage::ImageLuma8)
+        }
+        Format::R8G8 => {
+            ImageBuffer::from_raw(width, height, data.pixels).map(DynamicImage::ImageLumaA8)
+        }
+        Format::R8G8B8 => {
+            ImageBuffer::from_raw(width, height, data.pixels).map(DynamicImage::ImageRgb8)
+        }
+        Format::R8G8B8A8 => {
+            ImageBuffer::from_raw(width, height, data.pixels).map(DynamicImage::ImageRgba8)
+        }
+        Format::R16 => ImageBuffer::from_raw(width, height, to_u16(&data.pixels))
+            .map(DynamicImage::ImageLuma16),
+        Format::R16G16 => ImageBuffer::from_raw(width, height, to_u16(&data.pixels))
+            .map(DynamicImage::ImageLumaA16),
+        Format::R16G16B16 => {
+            ImageBuffer::from_raw(width, height, to_u16(&data.pixels)).map(DynamicImage::ImageRgb16)
+        }
+        Format::R16G16B16A16 => ImageBuffer::from_raw(width, height, to_u16(&data.pixels))
+            .map(DynamicImage::ImageRgba16),
+        Format::R32G32B32FLOAT => ImageBuffer::from_raw(width, height, to_f32(&data.pixels))
+            .map(DynamicImage::ImageRgb32F),
+        Format::R32G32B32A32FLOAT => ImageBuffer::from_raw(width, height, to_f32(&data.pixels))
+            .map(DynamicImage::ImageRgba32F),
+    };
+}
+
+fn to_u16(pixels: &[u8]) -> Vec<u16> {
+    return pixels
+        .chunks_exact(2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+        .collect();
+}
+
+fn to_f32(pixels: &[u8]) -> Vec<f32> {
+    return pixels
+        .chunks_exact(4)
+        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+}
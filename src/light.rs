@@ -0,0 +1,11 @@
+use cgmath::Vector3;
+use rust3d_derive::Std140;
+
+/// Directional light, mirrors the `Light` uniform block in `Lighting.glsl`
+#[derive(Clone, Copy, Std140)]
+pub struct Light {
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub specular: Vector3<f32>,
+}
@@ -1,29 +1,64 @@
-use cgmath::{perspective, SquareMatrix};
-use gl::types::{GLfloat, GLsizei, GLsizeiptr};
+use cgmath::perspective;
+use cgmath::SquareMatrix;
+use gl::types::GLenum;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::keyboard::Mod;
 
-use c_str_macro::c_str;
-use std::mem;
-use std::os::raw::c_void;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::str::FromStr;
 use std::time::Duration;
+use std::time::Instant;
 
+mod block_decoder;
+mod camera;
 mod common;
+mod compressed_texture;
+mod compute_shader;
+mod error;
 mod fps_manager;
+mod gl_object;
+mod gltf_loader;
+mod light;
+mod material;
+mod mesh;
+mod obj_loader;
+mod particles;
+mod preprocessor;
+mod program_cache;
+mod resources;
+mod sampler;
 mod shader;
+#[cfg(test)]
+mod shader_validation;
+mod shader_variants;
+mod skybox;
 mod texture_loader;
+mod uniform_buffer;
+mod uniform_names;
 mod vertex;
 
-use common::print_success_log;
+use camera::Camera;
+use common::{print_success_log, print_warning_log};
 use fps_manager::FPSManager;
+use gl_object::report_leaks;
+use gltf_loader::GltfScene;
+use light::Light;
+use material::Material;
+use mesh::Mesh;
+use obj_loader::ObjModel;
+use particles::Particles;
+use program_cache::ProgramCache;
+use sampler::{Filter, SamplerDesc};
 use shader::Shader;
-use texture_loader::TextureLoader;
-use vertex::Vertex;
+use shader_variants::{ShaderFeatures, ShaderVariants};
+use skybox::Skybox;
+use texture_loader::{TextureDesc, TextureLoader, VolumeFormat};
+use uniform_buffer::UniformBuffer;
+use vertex::{BasicVertex, ModelInstance};
 
 type Mat4 = cgmath::Matrix4<f32>;
 
@@ -32,11 +67,79 @@ const WINDOW_HEIGHT: u32 = 720;
 
 const FPS_LIMIT: u32 = 60;
 
-const FLOAT_NUM: usize = 8;
-const VERTEX_NUM: usize = 36;
-const BUFF_SIZE: usize = FLOAT_NUM * VERTEX_NUM;
+// Instances are placed from -GRID_SIZE to GRID_SIZE on the x and y axes
+const GRID_SIZE: i32 = 5;
+// Initial distance between the instances, adjustable in the control panel
+const GRID_SPACING: f32 = 3.0;
+
+// Points in the particle fountain
+const PARTICLE_NUM: usize = 2000;
+
+// Uniform buffer binding points shared by all programs
+const CAMERA_BINDING: u32 = 0;
+const LIGHT_BINDING: u32 = 1;
 
 fn main() {
+    // Parse arguments: [--resource-root <dir>] [--skybox <image>]
+    // [--skybox-faces <+x> <-x> <+y> <-y> <+z> <-z>] [--texture-array <image>,<image>,...]
+    // [--sprite-sheet <image> <columns>x<rows>]
+    // [--volume <raw file> <width>x<height>x<depth> <r8|r16|r32f|rgba8>] [model]
+    let mut resource_root = None;
+    let mut skybox_path = None;
+    let mut skybox_faces: Option<[PathBuf; 6]> = None;
+    let mut texture_array: Vec<PathBuf> = Vec::new();
+    let mut sprite_sheet: Option<(PathBuf, u32, u32)> = None;
+    let mut volume: Option<(PathBuf, [u32; 3], VolumeFormat)> = None;
+    let mut model_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--resource-root" => match args.next() {
+                Some(dir) => resource_root = Some(PathBuf::from(dir)),
+                None => panic!("--resource-root needs a directory"),
+            },
+            "--skybox" => match args.next() {
+                Some(image) => skybox_path = Some(PathBuf::from(image)),
+                None => panic!("--skybox needs an image"),
+            },
+            "--skybox-faces" => {
+                let faces: Vec<PathBuf> = args.by_ref().take(6).map(PathBuf::from).collect();
+                match faces.try_into() {
+                    Ok(faces) => skybox_faces = Some(faces),
+                    Err(_) => panic!("--skybox-faces needs six images: +x -x +y -y +z -z"),
+                }
+            }
+            "--texture-array" => match args.next() {
+                Some(images) => texture_array = images.split(',').map(PathBuf::from).collect(),
+                None => panic!("--texture-array needs comma-separated images"),
+            },
+            "--sprite-sheet" => match (args.next(), args.next().as_deref().and_then(parse_size)) {
+                (Some(image), Some([columns, rows])) => {
+                    sprite_sheet = Some((PathBuf::from(image), columns, rows))
+                }
+                _ => panic!("--sprite-sheet needs an image and <columns>x<rows>"),
+            },
+            "--volume" => match (
+                args.next(),
+                args.next().as_deref().and_then(parse_size),
+                args.next().as_deref().and_then(VolumeFormat::from_name),
+            ) {
+                (Some(path), Some(size), Some(format)) => {
+                    volume = Some((PathBuf::from(path), size, format))
+                }
+                _ => panic!("--volume needs a raw file, <width>x<height>x<depth> and a format"),
+            },
+            option if option.starts_with("--") => panic!("unknown option {}", option),
+            _ => model_path = Some(arg),
+        }
+    }
+
+    // Search resources in the given root, $RUST3D_RESOURCE_ROOT, the working directory
+    // (so that paths on the command line work), next to the executable and in the repository
+    // of a `target/<profile>` build. Built-in shaders and the sample model are embedded as a
+    // fallback.
+    resources::set_roots(resource_root);
+
     // Initialize SDL2
     let sdl_context = match sdl2::init() {
         Ok(sdl) => sdl,
@@ -74,10 +177,6 @@ fn main() {
     let mut fps_manager = FPSManager::new();
     print_success_log("Initialize FPS manager");
 
-    // Initialize texture loader
-    let mut texture_loader = TextureLoader::new();
-    print_success_log("Initialize texture loader");
-
     // Initialize OpenGL
     let _gl_context = match window.gl_create_context() {
         Ok(_gl_context) => _gl_context,
@@ -87,7 +186,51 @@ fn main() {
 
     // Load shaders
     gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as _);
-    let shader = Shader::new("src/shader/Basic.vert", "src/shader/Basic.frag");
+    let mut basic_builder = Shader::builder()
+        .vertex("src/shader/Basic.vert")
+        .fragment("src/shader/Basic.frag");
+    let mut normal_builder = Shader::builder()
+        .vertex("src/shader/Normals.vert")
+        .geometry("src/shader/Normals.geom")
+        .fragment("src/shader/Normals.frag");
+    let mut skybox_builder = Shader::builder()
+        .vertex("src/shader/Skybox.vert")
+        .fragment("src/shader/Skybox.frag");
+    let mut particles_builder = Shader::builder()
+        .vertex("src/shader/Particles.vert")
+        .fragment("src/shader/Particles.frag");
+    match ProgramCache::new(&ProgramCache::default_dir()) {
+        Ok(program_cache) => {
+            let program_cache = Rc::new(program_cache);
+            basic_builder = basic_builder.program_cache(&program_cache);
+            normal_builder = normal_builder.program_cache(&program_cache);
+            skybox_builder = skybox_builder.program_cache(&program_cache);
+            particles_builder = particles_builder.program_cache(&program_cache);
+        }
+        Err(e) => print_warning_log(&e.to_string()),
+    }
+    let mut shader_variants = ShaderVariants::from_builder(basic_builder);
+    if shader_variants.get(ShaderFeatures::NONE).is_none() {
+        if let Some((_, error)) = shader_variants.errors().next() {
+            panic!("{}", error);
+        }
+    }
+    let mut normal_shader = match normal_builder.build() {
+        Ok(shader) => shader,
+        Err(e) => panic!("{}", e),
+    };
+    let mut skybox = match skybox_builder.build() {
+        Ok(shader) => Skybox::new(shader),
+        Err(e) => panic!("{}", e),
+    };
+    let mut particles = match particles_builder.build() {
+        Ok(shader) => Particles::new(PARTICLE_NUM, shader),
+        Err(e) => panic!("{}", e),
+    };
+
+    // Initialize texture loader (after OpenGL so that textures are deleted before the context)
+    let mut texture_loader = TextureLoader::new();
+    print_success_log("Initialize texture loader");
 
     // Initialize ImGui
     let mut imgui_context = imgui::Context::create();
@@ -105,117 +248,177 @@ fn main() {
     print_success_log("Initialize imgui-sdl2");
 
     // Load sample texture
-    texture_loader.load(Path::new("resource/Texture.png"), "sample_texture");
-
-    // Set drawing buffer
-    #[rustfmt::skip]
-    let vertices: [f32; BUFF_SIZE] = [
-        // x, y, z, normal-x, normal-y, normal-z, tex_coord-x, tex_coord-y
-        0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0,
-        0.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0,
-        1.0, 1.0, 0.0, 0.0, 0.0, -1.0, 1.0, 0.0,
-
-        0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0,
-        1.0, 1.0, 0.0, 0.0, 0.0, -1.0, 1.0, 0.0,
-        1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 1.0, 1.0,
-
-        0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0,
-        0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0,
-        1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0,
-
-        0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0,
-        1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0,
-        1.0, 0.0, 1.0, 0.0, -1.0, 0.0, 1.0, 1.0,
-
-        0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0,
-        0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0,
-        1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0,
-
-        0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0,
-        1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0,
-        1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0,
-
-        0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
-        0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0,
-        1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0,
-
-        0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
-        1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0,
-        1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0,
-
-        1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0,
-        1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0,
-        1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0,
-
-        1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0,
-        1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0,
-        1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0,
-
-        0.0, 1.0, 1.0, -1.0, 0.0, 0.0, 0.0, 1.0,
-        0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0,
-        0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0,
-
-        0.0, 1.0, 1.0, -1.0, 0.0, 0.0, 0.0, 1.0,
-        0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0,
-        0.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 1.0,
-    ];
-
-    let vertex = Vertex::new(
-        (mem::size_of::<GLfloat>() * BUFF_SIZE) as GLsizeiptr,
-        vertices.as_ptr() as *const c_void,
-        gl::DYNAMIC_DRAW,
-        vec![gl::FLOAT, gl::FLOAT, gl::FLOAT],
-        vec![3, 3, 2],
-        mem::size_of::<GLfloat>() as GLsizei * FLOAT_NUM as i32,
-        VERTEX_NUM as i32,
-    );
+    if let Err(e) = texture_loader.load(
+        Path::new("resource/Texture.png"),
+        "sample_texture",
+        &TextureDesc::default(),
+    ) {
+        println!("Failed to load texture: {}", e);
+    }
+
+    // Load the skybox cubemap, which is also reflected by the materials
+    let skybox_result = if let Some(faces) = &skybox_faces {
+        Some(texture_loader.load_cubemap_faces(
+            faces.each_ref().map(PathBuf::as_path),
+            "skybox",
+            &TextureDesc::default(),
+        ))
+    } else {
+        skybox_path
+            .map(|path| texture_loader.load_cubemap(&path, "skybox", &TextureDesc::default()))
+    };
+    if let Some(Err(e)) = skybox_result {
+        println!("Failed to load skybox: {}", e);
+    }
+
+    // Layered textures are not drawn, but are listed in the control panel
+    if !texture_array.is_empty() {
+        let paths: Vec<&Path> = texture_array.iter().map(PathBuf::as_path).collect();
+        if let Err(e) = texture_loader.load_array(&paths, "texture_array", &TextureDesc::default())
+        {
+            println!("Failed to load texture array: {}", e);
+        }
+    }
+    if let Some((path, columns, rows)) = &sprite_sheet {
+        if let Err(e) = texture_loader.load_array_grid(
+            path,
+            *columns,
+            *rows,
+            "sprite_sheet",
+            &TextureDesc::default(),
+        ) {
+            println!("Failed to load sprite sheet: {}", e);
+        }
+    }
+    if let Some((path, size, format)) = &volume {
+        if let Err(e) =
+            texture_loader.load_volume(path, *size, *format, "volume", &TextureDesc::default())
+        {
+            println!("Failed to load volume: {}", e);
+        }
+    }
+    unsafe {
+        // Filter across the edges of cubemap faces
+        gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+    }
+
+    // Overrides the sampling of the material textures while "Nearest filtering" is on
+    let nearest_sampler = SamplerDesc {
+        min_filter: Filter::Nearest,
+        mag_filter: Filter::Nearest,
+        mipmap_filter: Some(Filter::Nearest),
+        ..SamplerDesc::default()
+    }
+    .create_sampler("Nearest filtering");
+
+    // Material for submeshes without `usemtl`
+    let mut default_material = Material::new("default");
+    default_material.diffuse_texture = Some(String::from("sample_texture"));
+
+    // Load model given as an argument (.obj, .gltf or .glb), or the sample cube
+    let model_path = model_path.unwrap_or_else(|| String::from("resource/Cube.obj"));
+    let model_path = Path::new(&model_path);
+    // The vertices of each mesh are kept to animate the "Wave" from
+    let (mut meshes, mesh_vertices): (Vec<Mesh>, Vec<Vec<BasicVertex>>) =
+        match model_path.extension().and_then(|ext| ext.to_str()) {
+            Some("gltf") | Some("glb") => {
+                let scene = match GltfScene::load(model_path, &mut texture_loader) {
+                    Ok(scene) => scene,
+                    Err(e) => panic!("Failed to load model: {}", e),
+                };
+                scene
+                    .primitives
+                    .iter()
+                    .map(|primitive| {
+                        (
+                            primitive.to_mesh(gl::DYNAMIC_DRAW),
+                            primitive.vertices.clone(),
+                        )
+                    })
+                    .unzip()
+            }
+            _ => {
+                let model = match ObjModel::load(model_path) {
+                    Ok(model) => model,
+                    Err(e) => panic!("Failed to load model: {}", e),
+                };
+                if let Err(e) = model.load_textures(&mut texture_loader) {
+                    println!("Failed to load texture: {}", e);
+                }
+                (
+                    vec![model.to_mesh(gl::DYNAMIC_DRAW, &default_material)],
+                    vec![model.vertices.clone()],
+                )
+            }
+        };
+    print_success_log("Load model");
+
+    // Grid of model instances drawn in one call per submesh
+    let mut grid_spacing = GRID_SPACING;
+    let instances = grid_instances(grid_spacing);
+    for mesh in &mut meshes {
+        mesh.vertex.add_instances(&instances, gl::DYNAMIC_DRAW);
+    }
 
     // View settings
     let mut blend = false;
     let mut cull_face = true;
     let mut depth_test = false;
     let mut wire = false;
-    let mut camera_x = 2.0f32;
-    let mut camera_y = 2.0f32;
-    let mut camera_z = 2.0f32;
+    let mut instanced_grid = false;
+    let mut show_normals = false;
+    let mut show_skybox = true;
+    let mut show_particles = false;
+    let mut wave = false;
+    // Whether the meshes are displaced and have to be restored when the wave stops
+    let mut waving = false;
+    let mut nearest_filtering = false;
+    let mut camera_x = 4.0f32;
+    let mut camera_y = 4.0f32;
+    let mut camera_z = 4.0f32;
     let alpha = 1.0f32;
-    
-    // Material settings
-    let mat_shininess = 0.1f32;
-    let mat_specular = cgmath::Vector3 {
-        x: 0.2f32,
-        y: 0.2f32,
-        z: 0.2f32,
-    };
-    
+    // Error of the last shader reload, shown until a reload succeeds
+    let mut shader_error: Option<String> = None;
+
     // Light settings
-    let light_ambient = cgmath::Vector3 {
-        x: 0.3f32,
-        y: 0.3f32,
-        z: 0.3f32,
-    };
-    let light_diffuse = cgmath::Vector3 {
-        x: 0.5f32,
-        y: 0.5f32,
-        z: 0.5f32,
-    };
-    let light_direction = cgmath::Vector3 {
-        x: 1.0f32,
-        y: 1.0f32,
-        z: -1.0f32,
-    };
-    let light_specular = cgmath::Vector3 {
-        x: 0.2f32,
-        y: 0.2f32,
-        z: 0.2f32,
+    let light = Light {
+        ambient: cgmath::Vector3::new(0.3f32, 0.3f32, 0.3f32),
+        diffuse: cgmath::Vector3::new(0.5f32, 0.5f32, 0.5f32),
+        direction: cgmath::Vector3::new(1.0f32, 1.0f32, -1.0f32),
+        specular: cgmath::Vector3::new(0.2f32, 0.2f32, 0.2f32),
     };
 
+    // Camera and light blocks read by every program
+    let mut camera_buffer = UniformBuffer::new(
+        "camera",
+        CAMERA_BINDING,
+        &Camera {
+            view: Mat4::identity(),
+            projection: Mat4::identity(),
+            position: cgmath::Vector3::new(camera_x, camera_y, camera_z),
+        },
+    );
+    let light_buffer = UniformBuffer::new("light", LIGHT_BINDING, &light);
+    unsafe {
+        shader_variants.set_uniform_block(uniform_names::CAMERA_BLOCK, &camera_buffer);
+        shader_variants.set_uniform_block(uniform_names::LIGHT_BLOCK, &light_buffer);
+        normal_shader.set_uniform_block(uniform_names::CAMERA_BLOCK, &camera_buffer);
+        skybox
+            .shader()
+            .set_uniform_block(uniform_names::CAMERA_BLOCK, &camera_buffer);
+        particles
+            .shader()
+            .set_uniform_block(uniform_names::CAMERA_BLOCK, &camera_buffer);
+    }
+
     // Main loop until end request (Event processing and Drawing process alternately)
     let mut event_pump = match sdl_context.event_pump() {
         Ok(event_pump) => event_pump,
         Err(e) => panic!("Failed to pump pending event: {:?}", e),
     };
 
+    let start_time = Instant::now();
+    let mut last_frame = Instant::now();
     'main: loop {
         // Execute event process
         for ev in event_pump.poll_iter() {
@@ -271,6 +474,48 @@ fn main() {
             }
         }
 
+        // Rebuild shaders edited on disk, keeping the old programs if they fail
+        let mut reloaded = false;
+        let mut reload_errors = Vec::new();
+        for result in [
+            shader_variants.reload_if_modified(),
+            normal_shader.reload_if_modified(),
+            skybox.reload_if_modified(),
+            particles.reload_if_modified(),
+        ] {
+            match result {
+                Ok(true) => reloaded = true,
+                Ok(false) => {}
+                Err(e) => reload_errors.push(e.to_string()),
+            }
+        }
+        if !reload_errors.is_empty() {
+            shader_error = Some(reload_errors.join("\n"));
+        } else if reloaded {
+            shader_error = None;
+            print_success_log("Reload shaders");
+        }
+
+        // Advance the particles by the time since the last frame
+        let delta = last_frame.elapsed().as_secs_f32();
+        last_frame = Instant::now();
+        if show_particles {
+            particles.update(delta);
+        }
+
+        // Displace the vertices along their normals, and put them back once the wave stops
+        if wave || waving {
+            let time = start_time.elapsed().as_secs_f32();
+            for (mesh, vertices) in meshes.iter_mut().zip(&mesh_vertices) {
+                if wave {
+                    mesh.vertex.update(0, &wave_vertices(vertices, time));
+                } else {
+                    mesh.vertex.update(0, vertices);
+                }
+            }
+            waving = wave;
+        }
+
         // Update view settings
         unsafe {
             if blend {
@@ -304,8 +549,7 @@ fn main() {
             gl::ClearColor(1.0, 1.0, 1.0, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
-            // Initialize matrices for model, view, projection
-            let model_matrix = Mat4::identity();
+            // Initialize matrices for view, projection
             let view_matrix = Mat4::look_at_rh(
                 cgmath::Point3 {
                     x: camera_x,
@@ -313,9 +557,9 @@ fn main() {
                     z: camera_z,
                 },
                 cgmath::Point3 {
-                    x: 0.5,
-                    y: 0.5,
-                    z: 0.5,
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
                 },
                 cgmath::Vector3 {
                     x: 0.0,
@@ -331,23 +575,74 @@ fn main() {
             );
 
             // Set matrix to shader
-            shader.use_program();
-            shader.set_mat(c_str!("uModel"), &model_matrix);
-            shader.set_mat(c_str!("uView"), &view_matrix);
-            shader.set_mat(c_str!("uProjection"), &projection_matrix);
-            shader.set_vec(c_str!("uViewPosition"), &cgmath::Vector3::new(camera_x, camera_y, camera_z));
-            shader.set_float(c_str!("uAlpha"), alpha);
-            shader.set_float(c_str!("uMaterial.shininess"), mat_shininess);
-            shader.set_vec(c_str!("uMaterial.specular"), &mat_specular);
-            shader.set_vec(c_str!("uLight.ambient"), &light_ambient);
-            shader.set_vec(c_str!("uLight.diffuse"), &light_diffuse);
-            shader.set_vec(c_str!("uLight.direction"), &light_direction);
-            shader.set_vec(c_str!("uLight.specular"), &light_specular);
-
-            // Draw vertices
-            gl::BindTexture(gl::TEXTURE_2D, texture_loader.get_from_id("sample_texture"));
-            vertex.draw();
+            camera_buffer.update(&Camera {
+                view: view_matrix,
+                projection: projection_matrix,
+                position: cgmath::Vector3::new(camera_x, camera_y, camera_z),
+            });
+
+            // Looked up every frame because the skybox can be unloaded in the control panel
+            let environment_map = texture_loader.get_from_id("skybox").ok();
+
+            // Draw the skybox first so that the scene covers it
+            if let (true, Some(cubemap)) = (show_skybox, environment_map) {
+                skybox.draw(cubemap);
+            }
+
+            // Draw each submesh with the shader variant for its material
+            let mut features = if instanced_grid {
+                ShaderFeatures::INSTANCED
+            } else {
+                ShaderFeatures::NONE
+            };
+            if let Some(cubemap) = environment_map {
+                // Unit 2 is not used by materials
+                features |= ShaderFeatures::ENVIRONMENT_MAP;
+                gl::ActiveTexture(gl::TEXTURE2);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            // Units 0 and 1 hold the diffuse and normal textures of the materials
+            if nearest_filtering {
+                gl::BindSampler(0, nearest_sampler.id());
+                gl::BindSampler(1, nearest_sampler.id());
+            }
+            for mesh in &meshes {
+                mesh.draw(
+                    &mut shader_variants,
+                    features,
+                    &texture_loader,
+                    &|shader: &Shader| {
+                        shader.set_float(uniform_names::ALPHA, alpha);
+                        if environment_map.is_some() {
+                            shader.set_sampler(uniform_names::ENVIRONMENT_MAP, 2);
+                        }
+                    },
+                );
+            }
+            gl::BindSampler(0, 0);
+            gl::BindSampler(1, 0);
             gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::ActiveTexture(gl::TEXTURE2);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+
+            // Draw vertex normals of triangle meshes with the geometry shader
+            if show_normals {
+                normal_shader.use_program();
+                normal_shader.set_float(uniform_names::NORMAL_LENGTH, 0.2);
+                for mesh in meshes
+                    .iter()
+                    .filter(|mesh| mesh.vertex.mode() == gl::TRIANGLES)
+                {
+                    normal_shader.set_mat(uniform_names::MODEL, &mesh.transform);
+                    mesh.vertex.draw();
+                }
+            }
+
+            if show_particles {
+                particles.draw();
+            }
 
             // Draw imgui windows
             imgui_sdl2_context.prepare_frame(
@@ -369,6 +664,11 @@ fn main() {
                         "Mouse Position: ({}, {})",
                         mouse_pos[0], mouse_pos[1]
                     ));
+
+                    // Submeshes with the material each one is drawn with
+                    for submesh in meshes.iter().flat_map(|mesh| &mesh.submeshes) {
+                        ui.text(format!("{}: {}", submesh.name, submesh.material.name));
+                    }
                 });
 
             // Control panel
@@ -379,8 +679,101 @@ fn main() {
                     ui.checkbox("Cull face", &mut cull_face);
                     ui.checkbox("Depth test", &mut depth_test);
                     ui.checkbox("Wire", &mut wire);
+                    ui.checkbox("Nearest filtering", &mut nearest_filtering);
+                    ui.checkbox("Instanced grid", &mut instanced_grid);
+                    if instanced_grid
+                        && imgui::Slider::new("Grid spacing", 1.0, 10.0)
+                            .build(&ui, &mut grid_spacing)
+                    {
+                        let instances = grid_instances(grid_spacing);
+                        for mesh in &mut meshes {
+                            mesh.vertex.update_instances(0, &instances);
+                        }
+                    }
+                    ui.checkbox("Show normals", &mut show_normals);
+                    ui.checkbox("Show skybox", &mut show_skybox);
+                    ui.checkbox("Show particles", &mut show_particles);
+                    ui.checkbox("Wave", &mut wave);
+                    if show_particles {
+                        ui.text(if particles.uses_compute() {
+                            "Particles: compute shader"
+                        } else {
+                            "Particles: CPU"
+                        });
+                    }
+
+                    // Materials whose textures are unloaded are drawn with their colors only
+                    if imgui::CollapsingHeader::new("Textures").build(&ui) {
+                        let mut textures: Vec<(String, GLenum)> = texture_loader
+                            .iter()
+                            .map(|(id, target)| (id.to_string(), target))
+                            .collect();
+                        textures.sort();
+                        for (id, target) in textures {
+                            if ui.small_button(format!("Unload##{}", id)) {
+                                if let Err(e) = texture_loader.unload(&id) {
+                                    println!("Failed to unload texture: {}", e);
+                                }
+                            }
+                            ui.same_line();
+                            ui.text(format!("{} ({})", id, texture_target_name(target)));
+                        }
+                    }
+
+                    // Active inputs of each variant built so far, as the driver reports them
+                    if imgui::CollapsingHeader::new("Shader interface").build(&ui) {
+                        for (features, shader) in shader_variants.iter() {
+                            imgui::TreeNode::new(features.to_string()).build(&ui, || {
+                                for attribute in shader.attributes() {
+                                    ui.text(format!(
+                                        "in {} (0x{:04X}[{}]) @ {}",
+                                        attribute.name,
+                                        attribute.gl_type,
+                                        attribute.size,
+                                        attribute.location
+                                    ));
+                                }
+
+                                let mut uniforms: Vec<_> = shader.uniforms().collect();
+                                uniforms.sort_by_key(|uniform| uniform.location);
+                                for uniform in uniforms {
+                                    ui.text(format!(
+                                        "uniform {} (0x{:04X}[{}]) @ {}",
+                                        uniform.name,
+                                        uniform.gl_type,
+                                        uniform.size,
+                                        uniform.location
+                                    ));
+                                }
+
+                                let mut blocks: Vec<_> = shader.uniform_blocks().collect();
+                                blocks.sort_by_key(|block| block.index);
+                                for block in blocks {
+                                    ui.text(format!(
+                                        "block {} ({} bytes) @ {}",
+                                        block.name, block.size, block.index
+                                    ));
+                                }
+                            });
+                        }
+                    }
                 });
 
+            // Compile or link errors of the last shader reload and of variants that failed
+            let variant_errors: Vec<String> = shader_variants
+                .errors()
+                .map(|(features, error)| format!("[{}] {}", features, error))
+                .collect();
+            if shader_error.is_some() || !variant_errors.is_empty() {
+                imgui::Window::new("Shader Error")
+                    .size([500.0, 200.0], imgui::Condition::FirstUseEver)
+                    .build(&ui, || {
+                        for error in shader_error.iter().chain(&variant_errors) {
+                            ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+                        }
+                    });
+            }
+
             imgui_sdl2_context.prepare_render(&ui, &window);
             imgui_renderer.render(ui);
 
@@ -391,4 +784,74 @@ fn main() {
         // FPS limitation
         std::thread::sleep(Duration::new(0, 1_000_000_000u32 / FPS_LIMIT));
     }
+
+    // Delete GL objects while the context is alive and report the ones left behind
+    drop(meshes);
+    drop(light_buffer);
+    drop(camera_buffer);
+    drop(normal_shader);
+    drop(skybox);
+    drop(particles);
+    drop(nearest_sampler);
+    drop(shader_variants);
+    texture_loader.clear();
+    report_leaks();
+}
+
+/// Size written as numbers separated by `x`, e.g. `64x64x32`
+fn parse_size<const N: usize>(text: &str) -> Option<[u32; N]> {
+    let numbers: Vec<u32> = text
+        .split('x')
+        .map(|number| number.parse().ok())
+        .collect::<Option<_>>()?;
+
+    return numbers.try_into().ok();
+}
+
+/// Name of a texture target for display
+fn texture_target_name(target: GLenum) -> &'static str {
+    return match target {
+        gl::TEXTURE_2D => "2D",
+        gl::TEXTURE_2D_ARRAY => "2D array",
+        gl::TEXTURE_3D => "3D",
+        gl::TEXTURE_CUBE_MAP => "cubemap",
+        _ => "other",
+    };
+}
+
+/// Instances placed `spacing` apart on a grid in the xy plane
+fn grid_instances(spacing: f32) -> Vec<ModelInstance> {
+    let mut instances = Vec::new();
+    for x in -GRID_SIZE..=GRID_SIZE {
+        for y in -GRID_SIZE..=GRID_SIZE {
+            instances.push(ModelInstance {
+                model: Mat4::from_translation(cgmath::Vector3::new(
+                    x as f32 * spacing,
+                    y as f32 * spacing,
+                    0.0,
+                )),
+            });
+        }
+    }
+
+    return instances;
+}
+
+/// Vertices moved along their normals by a wave travelling up the z axis
+fn wave_vertices(vertices: &[BasicVertex], time: f32) -> Vec<BasicVertex> {
+    return vertices
+        .iter()
+        .map(|vertex| {
+            let offset = 0.1 * (4.0 * vertex.position[2] - 3.0 * time).sin();
+            let mut position = vertex.position;
+            for (coordinate, normal) in position.iter_mut().zip(vertex.normal) {
+                *coordinate += offset * normal;
+            }
+
+            BasicVertex {
+                position,
+                ..*vertex
+            }
+        })
+        .collect();
 }
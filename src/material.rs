@@ -0,0 +1,336 @@
+use cgmath::Vector3;
+use gl::types::GLenum;
+use rust3d_derive::UniformStruct;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::shader::Shader;
+use crate::shader_variants::ShaderFeatures;
+use crate::texture_loader::{TextureDesc, TextureLoader};
+use crate::uniform_names;
+
+/// Surface parameters that map to `uMaterial` in Basic.frag
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+    pub shininess: f32,
+    pub alpha: f32,
+    /// How much of the environment map is mirrored, from 0 to 1
+    pub reflectivity: f32,
+    /// Id of the diffuse texture in `TextureLoader`
+    pub diffuse_texture: Option<String>,
+    /// File to load the diffuse texture from (`map_Kd`)
+    pub diffuse_texture_path: Option<PathBuf>,
+    /// Id of the tangent-space normal map in `TextureLoader`
+    pub normal_texture: Option<String>,
+    /// File to load the normal map from (`map_Bump`, `bump` or `norm`)
+    pub normal_texture_path: Option<PathBuf>,
+}
+
+/// Mirror of `struct Material` in Lighting.glsl
+#[derive(UniformStruct)]
+pub struct MaterialUniform {
+    ambient: Vector3<f32>,
+    diffuse: Vector3<f32>,
+    specular: Vector3<f32>,
+    shininess: f32,
+    alpha: f32,
+    reflectivity: f32,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Material {
+        let material = Material {
+            name: name.to_string(),
+            ambient: Vector3::new(1.0, 1.0, 1.0),
+            diffuse: Vector3::new(1.0, 1.0, 1.0),
+            specular: Vector3::new(0.2, 0.2, 0.2),
+            shininess: 0.1,
+            alpha: 1.0,
+            reflectivity: 0.0,
+            diffuse_texture: None,
+            diffuse_texture_path: None,
+            normal_texture: None,
+            normal_texture_path: None,
+        };
+
+        return material;
+    }
+
+    /// Load texture files into the texture loader if they have not been loaded yet
+    pub fn load_textures(&self, texture_loader: &mut TextureLoader) -> Result<()> {
+        let textures = [
+            (&self.diffuse_texture, &self.diffuse_texture_path),
+            (&self.normal_texture, &self.normal_texture_path),
+        ];
+        for (id, path) in textures {
+            if let (Some(id), Some(path)) = (id, path) {
+                if !texture_loader.contains(id) {
+                    texture_loader.load(path, id, &TextureDesc::default())?;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Shader features needed to draw the material. Textures that are not loaded are left out.
+    pub fn features(&self, texture_loader: &TextureLoader) -> ShaderFeatures {
+        let mut features = ShaderFeatures::NONE;
+        if texture_binding(&self.diffuse_texture, texture_loader).1 != 0 {
+            features |= ShaderFeatures::DIFFUSE_TEXTURE;
+        }
+        if texture_binding(&self.normal_texture, texture_loader).1 != 0 {
+            features |= ShaderFeatures::NORMAL_MAP;
+        }
+
+        return features;
+    }
+
+    /// Set `uMaterial.*` and bind the diffuse texture to texture unit 0 and
+    /// the normal map to unit 1. Unit 2 is left for the environment map.
+    /// `shader` should be the variant for `features`.
+    pub unsafe fn apply(&self, shader: &Shader, texture_loader: &TextureLoader) {
+        shader.set_struct(
+            uniform_names::MATERIAL,
+            &MaterialUniform {
+                ambient: self.ambient,
+                diffuse: self.diffuse,
+                specular: self.specular,
+                shininess: self.shininess,
+                alpha: self.alpha,
+                reflectivity: self.reflectivity,
+            },
+        );
+
+        let (diffuse_target, diffuse_id) = texture_binding(&self.diffuse_texture, texture_loader);
+        if diffuse_id != 0 {
+            shader.set_sampler(uniform_names::DIFFUSE_TEXTURE, 0);
+        }
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(diffuse_target, diffuse_id);
+
+        let (normal_target, normal_id) = texture_binding(&self.normal_texture, texture_loader);
+        if normal_id != 0 {
+            shader.set_sampler(uniform_names::NORMAL_TEXTURE, 1);
+        }
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(normal_target, normal_id);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+/// Target and GL name of the texture, or 0 on gl::TEXTURE_2D if it has no id or is not loaded
+fn texture_binding(id: &Option<String>, texture_loader: &TextureLoader) -> (GLenum, u32) {
+    let loaded = id.as_ref().and_then(|id| {
+        let target = texture_loader.get_target(id).ok()?;
+        let texture = texture_loader.get_from_id(id).ok()?;
+        Some((target, texture))
+    });
+
+    return loaded.unwrap_or((gl::TEXTURE_2D, 0));
+}
+
+/// Parse MTL source. Texture paths are resolved relative to `base_dir`.
+/// The path of parse errors is left empty.
+pub fn parse_mtl(source: &str, base_dir: &Path) -> Result<HashMap<String, Material>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<Material> = None;
+
+    for (i, raw_line) in source.lines().enumerate() {
+        let line_num = i + 1;
+
+        let line = match raw_line.find('#') {
+            Some(pos) => &raw_line[..pos],
+            None => raw_line,
+        }
+        .trim();
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            if args.is_empty() {
+                return Err(parse_error(line_num, "newmtl needs a name"));
+            }
+            current = Some(Material::new(&args.join(" ")));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some(material) => material,
+            None => return Err(parse_error(line_num, "statement before newmtl")),
+        };
+
+        match keyword {
+            "Ka" => material.ambient = parse_color(&args, line_num)?,
+            "Kd" => material.diffuse = parse_color(&args, line_num)?,
+            "Ks" => material.specular = parse_color(&args, line_num)?,
+            "Ns" => material.shininess = parse_float(&args, line_num)?,
+            "d" => material.alpha = parse_float(&args, line_num)?,
+            "Tr" => material.alpha = 1.0 - parse_float(&args, line_num)?,
+            "map_Kd" => {
+                let path = parse_texture_path(&args, base_dir, keyword, line_num)?;
+                material.diffuse_texture = Some(path.to_string_lossy().into_owned());
+                material.diffuse_texture_path = Some(path);
+            }
+            "map_Bump" | "bump" | "norm" => {
+                let path = parse_texture_path(&args, base_dir, keyword, line_num)?;
+                material.normal_texture = Some(path.to_string_lossy().into_owned());
+                material.normal_texture_path = Some(path);
+            }
+            // Ignore unsupported statements (illum, Ke, Ni, ...) and the PBR extension
+            // (Pr, Pm, Ps, ...), whose metallic factor is not a mirror reflectivity
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current.take() {
+        materials.insert(material.name.clone(), material);
+    }
+
+    return Ok(materials);
+}
+
+/// Texture options (-s, -o, -bm, ...) precede the file name, which is the last token
+fn parse_texture_path(
+    args: &[&str],
+    base_dir: &Path,
+    keyword: &str,
+    line_num: usize,
+) -> Result<PathBuf> {
+    return match args.last() {
+        Some(file) => Ok(base_dir.join(file)),
+        None => Err(parse_error(
+            line_num,
+            &format!("{} needs a file name", keyword),
+        )),
+    };
+}
+
+fn parse_error(line: usize, message: &str) -> Error {
+    return Error::Parse {
+        path: String::new(),
+        line,
+        message: message.to_string(),
+    };
+}
+
+fn parse_float(args: &[&str], line: usize) -> Result<f32> {
+    let arg = match args.first() {
+        Some(arg) => arg,
+        None => return Err(parse_error(line, "expected a value")),
+    };
+
+    return arg
+        .parse()
+        .map_err(|_| parse_error(line, &format!("invalid number '{}'", arg)));
+}
+
+fn parse_color(args: &[&str], line: usize) -> Result<Vector3<f32>> {
+    // A single value applies to all of r, g and b
+    let r = parse_float(args, line)?;
+    if args.len() < 3 {
+        return Ok(Vector3::new(r, r, r));
+    }
+    let g = parse_float(&args[1..], line)?;
+    let b = parse_float(&args[2..], line)?;
+
+    return Ok(Vector3::new(r, g, b));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> HashMap<String, Material> {
+        return parse_mtl(source, Path::new("models")).unwrap();
+    }
+
+    #[test]
+    fn colors_and_factors_are_parsed() {
+        let materials = parse(
+            "newmtl red\nKa 0.1 0.2 0.3\nKd 1 0 0\nKs 0.5\nNs 32\nd 0.75\n\
+             newmtl glass\nTr 0.25\n",
+        );
+
+        let red = &materials["red"];
+        assert_eq!(red.ambient, Vector3::new(0.1, 0.2, 0.3));
+        assert_eq!(red.diffuse, Vector3::new(1.0, 0.0, 0.0));
+        // A single value applies to all channels
+        assert_eq!(red.specular, Vector3::new(0.5, 0.5, 0.5));
+        assert_eq!(red.shininess, 32.0);
+        assert_eq!(red.alpha, 0.75);
+
+        assert_eq!(materials["glass"].alpha, 0.75);
+    }
+
+    #[test]
+    fn texture_paths_are_relative_to_the_base_dir() {
+        let materials = parse(
+            "newmtl brick\nmap_Kd -s 2 2 1 textures/brick.png\nmap_Bump textures/brick_n.png\n",
+        );
+
+        let brick = &materials["brick"];
+        assert_eq!(
+            brick.diffuse_texture_path.as_deref(),
+            Some(Path::new("models/textures/brick.png"))
+        );
+        assert_eq!(
+            brick.diffuse_texture.as_deref(),
+            Some(Path::new("models/textures/brick.png").to_str().unwrap())
+        );
+        assert_eq!(
+            brick.normal_texture_path.as_deref(),
+            Some(Path::new("models/textures/brick_n.png"))
+        );
+    }
+
+    #[test]
+    fn unknown_statements_are_ignored() {
+        let materials =
+            parse("newmtl plain\nillum 2\nKe 1 1 1\nNi 1.5\nPm 1\nPr 0.5\nmap_Ks spec.png\n");
+
+        let plain = &materials["plain"];
+        let default = Material::new("plain");
+        assert_eq!(plain.diffuse, default.diffuse);
+        assert_eq!(plain.specular, default.specular);
+        assert_eq!(plain.reflectivity, 0.0);
+        assert!(plain.diffuse_texture.is_none());
+        assert!(plain.normal_texture.is_none());
+    }
+
+    #[test]
+    fn parse_errors_have_the_line_number() {
+        let error = |source: &str| match parse_mtl(source, Path::new("")) {
+            Err(Error::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        };
+
+        assert_eq!(
+            error("Kd 1 1 1\n"),
+            (1, String::from("statement before newmtl"))
+        );
+        assert_eq!(
+            error("newmtl a\n\nNs x\n"),
+            (3, String::from("invalid number 'x'"))
+        );
+        assert_eq!(
+            error("newmtl a\nmap_Kd\n"),
+            (2, String::from("map_Kd needs a file name"))
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use cgmath::Matrix4;
+
+use crate::material::Material;
+use crate::shader::Shader;
+use crate::shader_variants::{ShaderFeatures, ShaderVariants};
+use crate::texture_loader::TextureLoader;
+use crate::uniform_names;
+use crate::vertex::Vertex;
+
+/// Range of elements in a mesh drawn with one material
+pub struct Submesh {
+    pub name: String,
+    pub first: i32,
+    pub count: i32,
+    pub material: Material,
+}
+
+/// Vertex buffer shared by one or more submeshes
+pub struct Mesh {
+    pub vertex: Vertex,
+    pub submeshes: Vec<Submesh>,
+    pub transform: Matrix4<f32>,
+}
+
+impl Mesh {
+    /// Draw each submesh with its own material, using the variant of `shader_variants`
+    /// for `features` plus the features of the material. `set_uniforms` is called after
+    /// a variant is bound to set uniforms that do not depend on the mesh.
+    /// With `ShaderFeatures::INSTANCED`, every instance added to the vertex is drawn.
+    pub unsafe fn draw(
+        &self,
+        shader_variants: &mut ShaderVariants,
+        features: ShaderFeatures,
+        texture_loader: &TextureLoader,
+        set_uniforms: &dyn Fn(&Shader),
+    ) {
+        for submesh in &self.submeshes {
+            let shader =
+                match shader_variants.get(features | submesh.material.features(texture_loader)) {
+                    Some(shader) => shader,
+                    // Skip submeshes whose variant failed to build
+                    None => continue,
+                };
+
+            shader.use_program();
+            set_uniforms(shader);
+            shader.set_mat(uniform_names::MODEL, &self.transform);
+            submesh.material.apply(shader, texture_loader);
+
+            if features.contains(ShaderFeatures::INSTANCED) {
+                self.vertex.draw_range_instanced(
+                    submesh.first,
+                    submesh.count,
+                    self.vertex.instance_num(),
+                );
+            } else {
+                self.vertex.draw_range(submesh.first, submesh.count);
+            }
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use cgmath::{Matrix4, SquareMatrix};
+use gl::types::GLenum;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::material::{parse_mtl, Material};
+use crate::mesh::{Mesh, Submesh};
+use crate::resources;
+use crate::texture_loader::TextureLoader;
+use crate::vertex::{BasicVertex, Vertex};
+
+/// Group of faces declared by `o` or `g` statement and drawn with one material.
+/// `first` and `count` are the range of the group in `ObjModel::indices`.
+pub struct ObjGroup {
+    pub name: String,
+    pub material: Option<String>,
+    pub first: i32,
+    pub count: i32,
+}
+
+/// Vertices of all groups are shared in one buffer
+pub struct ObjModel {
+    pub vertices: Vec<BasicVertex>,
+    pub indices: Vec<u32>,
+    pub groups: Vec<ObjGroup>,
+    pub material_libs: Vec<String>,
+    pub materials: HashMap<String, Material>,
+}
+
+impl ObjModel {
+    /// Load OBJ file and the MTL files it refers to (relative to the OBJ file)
+    pub fn load(path: &Path) -> Result<ObjModel> {
+        let source = resources::read_to_string(path)?;
+        let mut model =
+            ObjModel::parse(&source).map_err(|e| e.with_path(&path.to_string_lossy()))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        for lib in &model.material_libs {
+            let mtl_path = base_dir.join(lib);
+            let mtl_source = resources::read_to_string(&mtl_path)?;
+            let materials = parse_mtl(&mtl_source, base_dir)
+                .map_err(|e| e.with_path(&mtl_path.to_string_lossy()))?;
+            model.materials.extend(materials);
+        }
+
+        return Ok(model);
+    }
+
+    pub fn to_vertex(&self, usage: GLenum) -> Vertex {
+        let mut vertex = Vertex::new(&self.vertices, usage);
+        vertex.set_indices_compact(&self.indices, usage);
+
+        return vertex;
+    }
+
+    /// Create mesh with one submesh per group.
+    /// Groups without a known material are drawn with `default_material`.
+    pub fn to_mesh(&self, usage: GLenum, default_material: &Material) -> Mesh {
+        let submeshes = self
+            .groups
+            .iter()
+            .map(|group| Submesh {
+                name: group.name.clone(),
+                first: group.first,
+                count: group.count,
+                material: self.material(group).unwrap_or(default_material).clone(),
+            })
+            .collect();
+
+        return Mesh {
+            vertex: self.to_vertex(usage),
+            submeshes,
+            transform: Matrix4::identity(),
+        };
+    }
+
+    pub fn material(&self, group: &ObjGroup) -> Option<&Material> {
+        return group
+            .material
+            .as_ref()
+            .and_then(|name| self.materials.get(name));
+    }
+
+    pub fn load_textures(&self, texture_loader: &mut TextureLoader) -> Result<()> {
+        for material in self.materials.values() {
+            material.load_textures(texture_loader)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Parse OBJ source. The path of parse errors is left empty.
+    pub fn parse(source: &str) -> Result<ObjModel> {
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+
+        let mut vertices: Vec<BasicVertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut vertex_map: HashMap<FaceVertex, u32> = HashMap::new();
+
+        let mut material_libs = Vec::new();
+        let mut groups = vec![ObjGroup {
+            name: String::from("default"),
+            material: None,
+            first: 0,
+            count: 0,
+        }];
+
+        for (i, raw_line) in source.lines().enumerate() {
+            let line_num = i + 1;
+
+            // Strip comments and surrounding whitespace
+            let line = match raw_line.find('#') {
+                Some(pos) => &raw_line[..pos],
+                None => raw_line,
+            }
+            .trim();
+
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => positions.push(parse_floats::<3>(&args, line_num)?),
+                "vn" => normals.push(parse_floats::<3>(&args, line_num)?),
+                "vt" => {
+                    // v defaults to 0, and the third (w) coordinate is not used
+                    let uv = if args.len() < 2 {
+                        let [u] = parse_floats::<1>(&args, line_num)?;
+                        [u, 0.0]
+                    } else {
+                        parse_floats::<2>(&args[..2], line_num)?
+                    };
+                    tex_coords.push(uv);
+                }
+                "o" | "g" => {
+                    let name = if args.is_empty() {
+                        String::from("default")
+                    } else {
+                        args.join(" ")
+                    };
+
+                    // Reuse the current group if no face has been added to it yet
+                    let current = groups.last_mut().unwrap();
+                    if current.count == 0 {
+                        current.name = name;
+                    } else {
+                        let material = current.material.clone();
+                        groups.push(ObjGroup {
+                            name,
+                            material,
+                            first: indices.len() as i32,
+                            count: 0,
+                        });
+                    }
+                }
+                "mtllib" => material_libs.extend(args.iter().map(|lib| lib.to_string())),
+                "usemtl" => {
+                    if args.is_empty() {
+                        return Err(parse_error(line_num, "usemtl needs a material name"));
+                    }
+                    let material = Some(args.join(" "));
+
+                    // Split the group so that each submesh has exactly one material
+                    let current = groups.last_mut().unwrap();
+                    if current.count == 0 {
+                        current.material = material;
+                    } else {
+                        let name = current.name.clone();
+                        groups.push(ObjGroup {
+                            name,
+                            material,
+                            first: indices.len() as i32,
+                            count: 0,
+                        });
+                    }
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(parse_error(line_num, "face needs at least 3 vertices"));
+                    }
+
+                    let mut corners = Vec::with_capacity(args.len());
+                    for arg in &args {
+                        corners.push(parse_face_vertex(
+                            arg,
+                            line_num,
+                            positions.len(),
+                            tex_coords.len(),
+                            normals.len(),
+                        )?);
+                    }
+
+                    // Use flat face normal for corners without normal
+                    let flat_normal = face_normal(&[
+                        positions[corners[0].0],
+                        positions[corners[1].0],
+                        positions[corners[2].0],
+                    ]);
+
+                    let mut face_indices = Vec::with_capacity(corners.len());
+                    for corner in &corners {
+                        let tex_coord = match corner.1 {
+                            Some(t) => tex_coords[t],
+                            None => [0.0, 0.0],
+                        };
+
+                        // Share vertices with identical position / tex_coord / normal
+                        let index = match corner.2 {
+                            Some(n) => match vertex_map.get(corner) {
+                                Some(&index) => index,
+                                None => {
+                                    let index = push_vertex(
+                                        &mut vertices,
+                                        positions[corner.0],
+                                        normals[n],
+                                        tex_coord,
+                                    );
+                                    vertex_map.insert(*corner, index);
+                                    index
+                                }
+                            },
+                            None => push_vertex(
+                                &mut vertices,
+                                positions[corner.0],
+                                flat_normal,
+                                tex_coord,
+                            ),
+                        };
+                        face_indices.push(index);
+                    }
+
+                    // Triangulate polygon as a fan around the first vertex
+                    for k in 1..face_indices.len() - 1 {
+                        indices.extend_from_slice(&[
+                            face_indices[0],
+                            face_indices[k],
+                            face_indices[k + 1],
+                        ]);
+                    }
+                    groups.last_mut().unwrap().count += (face_indices.len() as i32 - 2) * 3;
+                }
+                // Ignore unsupported statements (s, l, p, ...)
+                _ => {}
+            }
+        }
+
+        groups.retain(|group| group.count > 0);
+
+        return Ok(ObjModel {
+            vertices,
+            indices,
+            groups,
+            material_libs,
+            materials: HashMap::new(),
+        });
+    }
+}
+
+/// Indices of position, tex_coord and normal of a face corner
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+fn push_vertex(
+    vertices: &mut Vec<BasicVertex>,
+    position: [f32; 3],
+    normal: [f32; 3],
+    tex_coord: [f32; 2],
+) -> u32 {
+    vertices.push(BasicVertex {
+        position,
+        normal,
+        tex_coord,
+    });
+
+    return (vertices.len() - 1) as u32;
+}
+
+fn parse_error(line: usize, message: &str) -> Error {
+    return Error::Parse {
+        path: String::new(),
+        line,
+        message: message.to_string(),
+    };
+}
+
+fn parse_floats<const N: usize>(args: &[&str], line: usize) -> Result<[f32; N]> {
+    if args.len() < N {
+        return Err(parse_error(
+            line,
+            &format!("expected {} values but found {}", N, args.len()),
+        ));
+    }
+
+    let mut values = [0.0f32; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg
+            .parse()
+            .map_err(|_| parse_error(line, &format!("invalid number '{}'", arg)))?;
+    }
+
+    return Ok(values);
+}
+
+/// Convert 1-based (or negative, relative) OBJ index into 0-based index
+fn resolve_index(token: &str, count: usize, line: usize) -> Result<usize> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| parse_error(line, &format!("invalid index '{}'", token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(
+            line,
+            &format!("index {} is out of range", index),
+        ));
+    }
+
+    return Ok(resolved as usize);
+}
+
+/// Parse `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_face_vertex(
+    arg: &str,
+    line: usize,
+    position_num: usize,
+    tex_coord_num: usize,
+    normal_num: usize,
+) -> Result<FaceVertex> {
+    let mut parts = arg.split('/');
+
+    let position = resolve_index(parts.next().unwrap_or(""), position_num, line)?;
+    let tex_coord = match parts.next() {
+        Some(t) if !t.is_empty() => Some(resolve_index(t, tex_coord_num, line)?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(n) if !n.is_empty() => Some(resolve_index(n, normal_num, line)?),
+        _ => None,
+    };
+
+    return Ok((position, tex_coord, normal));
+}
+
+pub fn face_normal(p: &[[f32; 3]; 3]) -> [f32; 3] {
+    let u = [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]];
+    let v = [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+
+    if len == 0.0 {
+        return [0.0, 0.0, 0.0];
+    }
+
+    return [n[0] / len, n[1] / len, n[2] / len];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let model =
+            ObjModel::parse(&format!("{}vt 0.5 0.25\nf -4/-1 -3/-1 -2/-1\n", QUAD)).unwrap();
+
+        let positions: Vec<[f32; 3]> = model
+            .indices
+            .iter()
+            .map(|index| model.vertices[*index as usize].position)
+            .collect();
+        assert_eq!(
+            positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]
+        );
+        assert!(model
+            .vertices
+            .iter()
+            .all(|vertex| vertex.tex_coord == [0.5, 0.25]));
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let model = ObjModel::parse(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1 4//1\n", QUAD)).unwrap();
+
+        // Corners with the same position and normal share one vertex
+        assert_eq!(model.vertices.len(), 4);
+        assert_eq!(model.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(model.groups.len(), 1);
+        assert_eq!(model.groups[0].count, 6);
+    }
+
+    #[test]
+    fn faces_without_normals_get_the_face_normal() {
+        let model = ObjModel::parse(&format!("{}f 1 2 3\n", QUAD)).unwrap();
+
+        assert!(model
+            .vertices
+            .iter()
+            .all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn groups_and_materials_split_submeshes() {
+        let source = format!(
+            "{}o first\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\ng second\nf 1 2 4\n",
+            QUAD
+        );
+        let model = ObjModel::parse(&source).unwrap();
+
+        let groups: Vec<(&str, Option<&str>, i32, i32)> = model
+            .groups
+            .iter()
+            .map(|group| {
+                (
+                    group.name.as_str(),
+                    group.material.as_deref(),
+                    group.first,
+                    group.count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("first", Some("red"), 0, 3),
+                ("first", Some("blue"), 3, 3),
+                // A new group keeps the current material
+                ("second", Some("blue"), 6, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_groups_are_dropped() {
+        let model = ObjModel::parse(&format!("{}g unused\ng used\nf 1 2 3\n", QUAD)).unwrap();
+
+        assert_eq!(model.groups.len(), 1);
+        assert_eq!(model.groups[0].name, "used");
+    }
+
+    #[test]
+    fn single_tex_coord_defaults_v_to_zero() {
+        let model = ObjModel::parse(&format!("{}vt 0.75\nf 1/1 2/1 3/1\n", QUAD)).unwrap();
+
+        assert_eq!(model.vertices[0].tex_coord, [0.75, 0.0]);
+    }
+
+    #[test]
+    fn parse_errors_have_the_line_number() {
+        let error = |source: &str| match ObjModel::parse(source) {
+            Err(Error::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        };
+
+        assert_eq!(
+            error("v 0 0 0\n\nf 1 2 3\n"),
+            (3, String::from("index 2 is out of range"))
+        );
+        assert_eq!(
+            error("# comment\nv 0 x 0\n"),
+            (2, String::from("invalid number 'x'"))
+        );
+        assert_eq!(
+            error("v 0 0\n"),
+            (1, String::from("expected 3 values but found 2"))
+        );
+        assert_eq!(
+            error(&format!("{}f 1 2\n", QUAD)),
+            (5, String::from("face needs at least 3 vertices"))
+        );
+        assert_eq!(error("vt\n").0, 1);
+    }
+}
@@ -0,0 +1,184 @@
+use std::f32::consts::PI;
+
+use crate::common::print_warning_log;
+use crate::compute_shader::{memory_barrier, supports_compute, ComputeShader};
+use crate::error::Result;
+use crate::shader::Shader;
+use crate::uniform_names;
+use crate::vertex::{Particle, StreamMode, Vertex};
+
+/// Seconds from the launch of a particle to its next launch
+const LIFETIME: f32 = 2.0;
+/// Upward launch speed in units per second
+const LAUNCH_SPEED: f32 = 5.0;
+/// Largest sideways launch speed in units per second
+const SPREAD: f32 = 1.5;
+/// Acceleration along z, which is up in the scene
+const GRAVITY: f32 = -5.0;
+
+/// Fountain of points launched from the origin. The particles are simulated by
+/// Particles.comp in the vertex buffer, or on the CPU and streamed to the vertex
+/// buffer every frame when compute shaders are not supported.
+pub struct Particles {
+    /// Simulated on the CPU when there is no compute shader
+    particles: Vec<Particle>,
+    vertex: Vertex,
+    shader: Shader,
+    compute: Option<ComputeShader>,
+}
+
+impl Particles {
+    /// `shader` is built from Particles.vert and Particles.frag, and needs the
+    /// Camera block bound with `shader().set_uniform_block`
+    pub fn new(count: usize, shader: Shader) -> Particles {
+        // Spread the launches over the lifetime so that the fountain is continuous
+        let particles: Vec<Particle> = (0..count)
+            .map(|index| {
+                let age = LIFETIME * index as f32 / count as f32;
+                let velocity = launch_velocity(index as u32, 0);
+                Particle {
+                    position: position(velocity, age),
+                    velocity: [velocity[0], velocity[1], velocity[2], 0.0],
+                }
+            })
+            .collect();
+
+        let compute = if supports_compute() {
+            match ComputeShader::new("src/shader/Particles.comp") {
+                Ok(compute) => Some(compute),
+                Err(e) => {
+                    print_warning_log(&format!("{}\nParticles are simulated on the CPU", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut vertex = match compute {
+            // Only written by the compute shader after the first upload
+            Some(_) => Vertex::new(&particles, gl::DYNAMIC_COPY),
+            None => {
+                let mut vertex = Vertex::new_streaming::<Particle>(count, StreamMode::Persistent);
+                vertex.stream(&particles);
+                vertex
+            }
+        };
+        vertex.set_mode(gl::POINTS);
+
+        return Particles {
+            particles,
+            vertex,
+            shader,
+            compute,
+        };
+    }
+
+    pub fn shader(&self) -> &Shader {
+        return &self.shader;
+    }
+
+    /// Whether the particles are simulated by a compute shader
+    pub fn uses_compute(&self) -> bool {
+        return self.compute.is_some();
+    }
+
+    pub fn reload_if_modified(&mut self) -> Result<bool> {
+        let mut reloaded = self.shader.reload_if_modified()?;
+        if let Some(compute) = &mut self.compute {
+            reloaded |= compute.reload_if_modified()?;
+        }
+
+        return Ok(reloaded);
+    }
+
+    /// Advance the simulation by `delta` seconds
+    pub fn update(&mut self, delta: f32) {
+        if let Some(compute) = &self.compute {
+            unsafe {
+                compute.use_program();
+                compute.set_float(uniform_names::PARTICLE_DELTA, delta);
+                compute.set_float(uniform_names::PARTICLE_LIFETIME, LIFETIME);
+                compute.set_float(uniform_names::PARTICLE_LAUNCH_SPEED, LAUNCH_SPEED);
+                compute.set_float(uniform_names::PARTICLE_SPREAD, SPREAD);
+                compute.set_float(uniform_names::PARTICLE_GRAVITY, GRAVITY);
+
+                self.vertex.bind_storage(0);
+                compute.dispatch_size(self.particles.len() as u32, 1, 1);
+                // The next draw reads the positions as vertex attributes
+                memory_barrier(gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+            }
+            return;
+        }
+
+        for (index, particle) in self.particles.iter_mut().enumerate() {
+            let mut age = particle.position[3] + delta;
+            let mut launch = particle.velocity[3];
+            let mut velocity = [
+                particle.velocity[0],
+                particle.velocity[1],
+                particle.velocity[2],
+            ];
+
+            // Launch again from the origin once the lifetime is over
+            if age >= LIFETIME {
+                age %= LIFETIME;
+                launch += 1.0;
+                velocity = launch_velocity(index as u32, launch as u32);
+            }
+
+            particle.position = position(velocity, age);
+            particle.velocity = [velocity[0], velocity[1], velocity[2], launch];
+        }
+
+        self.vertex.stream(&self.particles);
+    }
+
+    pub unsafe fn draw(&self) {
+        self.shader.use_program();
+        self.shader
+            .set_float(uniform_names::PARTICLE_LIFETIME, LIFETIME);
+
+        // Point size is written by the vertex shader
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        self.vertex.draw();
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+    }
+}
+
+/// Position `age` seconds after a launch from the origin, with the age as w
+fn position(velocity: [f32; 3], age: f32) -> [f32; 4] {
+    return [
+        velocity[0] * age,
+        velocity[1] * age,
+        velocity[2] * age + 0.5 * GRAVITY * age * age,
+        age,
+    ];
+}
+
+/// Velocity of the `launch`-th launch of a particle, pseudo-random but reproducible.
+/// Particles.comp computes the same velocities.
+fn launch_velocity(index: u32, launch: u32) -> [f32; 3] {
+    let seed = hash(index ^ hash(launch));
+    let angle = 2.0 * PI * random(seed);
+    let speed = SPREAD * random(seed.wrapping_add(1)).sqrt();
+
+    return [angle.cos() * speed, angle.sin() * speed, LAUNCH_SPEED];
+}
+
+/// Integer hash with good avalanche (lowbias32)
+fn hash(value: u32) -> u32 {
+    let mut x = value;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+
+    return x;
+}
+
+/// Value in [0, 1) from the top 24 bits of the hash of `seed`
+fn random(seed: u32) -> f32 {
+    return (hash(seed) >> 8) as f32 / (1 << 24) as f32;
+}
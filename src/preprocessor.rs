@@ -0,0 +1,315 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::resources;
+
+/// GLSL source with `#include`s expanded and defines injected
+pub struct PreprocessedSource {
+    pub code: String,
+    /// File of each source string number used in the `#line` directives,
+    /// starting with the root file as number 0
+    pub files: Vec<String>,
+}
+
+/// Expand `#include "file"` (relative to the including file) and add
+/// `#define name value` after `#version`. `#line` directives are inserted around
+/// included code, so compile errors refer to the original file and line.
+pub fn preprocess(path: &str, defines: &[(String, String)]) -> Result<PreprocessedSource> {
+    let mut preprocessor = Preprocessor {
+        code: String::new(),
+        files: Vec::new(),
+        stack: Vec::new(),
+    };
+    preprocessor.expand(Path::new(path), Some(defines))?;
+
+    return Ok(PreprocessedSource {
+        code: preprocessor.code,
+        files: preprocessor.files,
+    });
+}
+
+struct Preprocessor {
+    code: String,
+    files: Vec<String>,
+    /// Files being expanded, to detect include cycles
+    stack: Vec<PathBuf>,
+}
+
+impl Preprocessor {
+    /// Append the expanded file. Defines are only given for the root file.
+    fn expand(&mut self, path: &Path, defines: Option<&[(String, String)]>) -> Result<()> {
+        let source = resources::read_to_string(path)?;
+        let path_str = path.to_string_lossy().into_owned();
+        let number = self.file_number(&path_str);
+        self.stack.push(canonical(path));
+
+        // Defines go right after `#version`, which must come first, or at the top without it
+        let has_version = source
+            .lines()
+            .any(|line| line.trim_start().starts_with("#version"));
+        let mut defines = defines;
+        if !has_version {
+            self.push_defines(defines.take(), 1, number);
+        }
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if defines.is_some() && line.trim_start().starts_with("#version") {
+                self.code.push_str(line);
+                self.code.push('\n');
+                self.push_defines(defines.take(), line_number + 1, number);
+                continue;
+            }
+
+            let name = match parse_include(line) {
+                Some(Ok(name)) => name,
+                Some(Err(message)) => return Err(parse_error(&path_str, line_number, message)),
+                None => {
+                    self.code.push_str(line);
+                    self.code.push('\n');
+                    continue;
+                }
+            };
+
+            let include = path.parent().unwrap_or(Path::new("")).join(name);
+            if !resources::exists(&include) {
+                return Err(parse_error(
+                    &path_str,
+                    line_number,
+                    format!("included file {} not found", include.display()),
+                ));
+            }
+            if self.stack.contains(&canonical(&include)) {
+                let cycle: Vec<String> = self
+                    .stack
+                    .iter()
+                    .chain([&canonical(&include)])
+                    .map(|file| file.display().to_string())
+                    .collect();
+                return Err(parse_error(
+                    &path_str,
+                    line_number,
+                    format!("include cycle: {}", cycle.join(" -> ")),
+                ));
+            }
+
+            let include_number = self.file_number(&include.to_string_lossy());
+            self.code.push_str(&format!("#line 1 {}\n", include_number));
+            self.expand(&include, None)?;
+            self.code
+                .push_str(&format!("#line {} {}\n", line_number + 1, number));
+        }
+
+        self.stack.pop();
+
+        return Ok(());
+    }
+
+    /// Add defines, then continue numbering at `next_line`
+    fn push_defines(
+        &mut self,
+        defines: Option<&[(String, String)]>,
+        next_line: usize,
+        number: usize,
+    ) {
+        let defines = match defines {
+            Some(defines) if !defines.is_empty() => defines,
+            _ => return,
+        };
+
+        for (name, value) in defines {
+            self.code.push_str(&format!("#define {} {}\n", name, value));
+        }
+        self.code
+            .push_str(&format!("#line {} {}\n", next_line, number));
+    }
+
+    /// Source string number of the file, adding it if it is new
+    fn file_number(&mut self, path: &str) -> usize {
+        return match self.files.iter().position(|file| file == path) {
+            Some(number) => number,
+            None => {
+                self.files.push(path.to_string());
+                self.files.len() - 1
+            }
+        };
+    }
+}
+
+/// `Some(Ok(file))` for `#include "file"`, `Some(Err(_))` for a malformed include
+fn parse_include(line: &str) -> Option<std::result::Result<&str, String>> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let argument = directive.strip_prefix("include")?.trim();
+
+    return Some(
+        argument
+            .strip_prefix('"')
+            .and_then(|argument| argument.strip_suffix('"'))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| format!("expected #include \"file\", found {}", line.trim())),
+    );
+}
+
+/// Canonical path of the file found on disk, or the path itself for embedded files
+fn canonical(path: &Path) -> PathBuf {
+    return resources::find(path)
+        .ok()
+        .and_then(|file| fs::canonicalize(file).ok())
+        .unwrap_or_else(|| path.to_path_buf());
+}
+
+fn parse_error(path: &str, line: usize, message: String) -> Error {
+    return Error::Parse {
+        path: path.to_string(),
+        line,
+        message,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust3d_pp_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, code) in files {
+            fs::write(dir.join(file), code).unwrap();
+        }
+
+        return dir;
+    }
+
+    /// Source string number and line the compiler reports for the line containing
+    /// `marker`, following the `#line` directives like a GLSL compiler does
+    fn location(code: &str, marker: &str) -> (usize, usize) {
+        let (mut source, mut line) = (0, 1);
+        for text in code.lines() {
+            if let Some(directive) = text.strip_prefix("#line ") {
+                let numbers: Vec<usize> = directive
+                    .split_whitespace()
+                    .map(|number| number.parse().unwrap())
+                    .collect();
+                line = numbers[0];
+                source = numbers[1];
+                continue;
+            }
+            if text.contains(marker) {
+                return (source, line);
+            }
+            line += 1;
+        }
+
+        panic!("{} not found in\n{}", marker, code);
+    }
+
+    #[test]
+    fn nested_includes_keep_file_and_line() {
+        let dir = write_files(
+            "nested",
+            &[
+                (
+                    "main.vert",
+                    "#version 330 core\n#include \"a.glsl\"\nvoid main() { main_marker; }\n",
+                ),
+                (
+                    "a.glsl",
+                    "// a\n// a\n#include \"b.glsl\"\nfloat a_marker;\n",
+                ),
+                ("b.glsl", "// b\nfloat b_marker;\n"),
+            ],
+        );
+        let main = dir.join("main.vert");
+        let source = preprocess(main.to_str().unwrap(), &[]);
+        fs::remove_dir_all(&dir).unwrap();
+        let source = source.unwrap();
+
+        let file = |number: usize| PathBuf::from(&source.files[number]);
+        assert_eq!(file(0), main);
+        assert_eq!(file(1), dir.join("a.glsl"));
+        assert_eq!(file(2), dir.join("b.glsl"));
+
+        assert_eq!(location(&source.code, "b_marker"), (2, 2));
+        assert_eq!(location(&source.code, "a_marker"), (1, 4));
+        assert_eq!(location(&source.code, "main_marker"), (0, 3));
+    }
+
+    #[test]
+    fn error_in_nested_include_names_included_file() {
+        let dir = write_files(
+            "nested_error",
+            &[
+                ("main.vert", "#version 330 core\n#include \"a.glsl\"\n"),
+                ("a.glsl", "// a\n#include \"b.glsl\"\n"),
+                ("b.glsl", "// b\n// b\n#include b.glsl\n"),
+            ],
+        );
+        let main = dir.join("main.vert");
+        let result = preprocess(main.to_str().unwrap(), &[]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Err(Error::Parse { path, line, .. }) => {
+                assert_eq!(PathBuf::from(path), dir.join("b.glsl"));
+                assert_eq!(line, 3);
+            }
+            result => panic!("expected a parse error, got {:?}", result.map(|s| s.code)),
+        }
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("main.vert", "#version 330 core\n#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "// b\n#include \"a.glsl\"\n"),
+            ],
+        );
+        let main = dir.join("main.vert");
+        let result = preprocess(main.to_str().unwrap(), &[]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Err(Error::Parse {
+                path,
+                line,
+                message,
+            }) => {
+                assert_eq!(PathBuf::from(path), dir.join("b.glsl"));
+                assert_eq!(line, 2);
+                assert!(message.starts_with("include cycle"), "{}", message);
+            }
+            result => panic!("expected a parse error, got {:?}", result.map(|s| s.code)),
+        }
+    }
+
+    #[test]
+    fn defines_follow_version_without_shifting_lines() {
+        let dir = write_files(
+            "defines",
+            &[(
+                "main.vert",
+                "// header\n#version 330 core\nfloat first_marker;\nfloat second_marker;\n",
+            )],
+        );
+        let main = dir.join("main.vert");
+        let defines = [
+            (String::from("COUNT"), String::from("4")),
+            (String::from("USE_FOG"), String::from("1")),
+        ];
+        let source = preprocess(main.to_str().unwrap(), &defines);
+        fs::remove_dir_all(&dir).unwrap();
+        let source = source.unwrap();
+
+        let lines: Vec<&str> = source.code.lines().collect();
+        assert_eq!(lines[1], "#version 330 core");
+        assert_eq!(lines[2], "#define COUNT 4");
+        assert_eq!(lines[3], "#define USE_FOG 1");
+        assert_eq!(location(&source.code, "first_marker"), (0, 3));
+        assert_eq!(location(&source.code, "second_marker"), (0, 4));
+    }
+}
@@ -0,0 +1,269 @@
+use core::ffi::c_void;
+use gl::types::*;
+
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::{gl_supports, print_warning_log};
+use crate::error::{Error, Result};
+
+/// First bytes of a cache file, followed by the key, the binary format and the binary
+const MAGIC: &[u8; 4] = b"R3DP";
+
+/// Linked program binaries on disk, one file per program name. The file holds the
+/// key of the preprocessed sources and the driver it was built from, so binaries
+/// for older sources or another driver are never used and are overwritten by the
+/// next `store`. Binaries the driver rejects are deleted, so callers fall back to
+/// compiling.
+pub struct ProgramCache {
+    dir: PathBuf,
+    /// Vendor, renderer and version of the driver
+    driver: String,
+}
+
+impl ProgramCache {
+    /// Cache in `dir`, which is created if needed.
+    /// Needs OpenGL 4.1 or GL_ARB_get_program_binary, with at least one binary format.
+    pub fn new(dir: &Path) -> Result<ProgramCache> {
+        let mut format_num = 0;
+        if gl::GetProgramBinary::is_loaded() && gl_supports((4, 1), "GL_ARB_get_program_binary") {
+            unsafe {
+                gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_num);
+            }
+        }
+        if format_num == 0 {
+            return Err(Error::Unsupported {
+                feature: "Program binary cache",
+                requirement: "OpenGL 4.1 or GL_ARB_get_program_binary with a binary format",
+            });
+        }
+
+        fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+
+        let driver: Vec<String> = [gl::VENDOR, gl::RENDERER, gl::VERSION]
+            .iter()
+            .map(|name| unsafe { gl_string(*name) })
+            .collect();
+
+        return Ok(ProgramCache {
+            dir: dir.to_path_buf(),
+            driver: driver.join("\n"),
+        });
+    }
+
+    /// Cache directory of the app in the user's cache directory (`%LOCALAPPDATA%`,
+    /// `$XDG_CACHE_HOME` or `~/.cache`), or in the temp directory without one
+    pub fn default_dir() -> PathBuf {
+        let user_dir = env::var_os("LOCALAPPDATA")
+            .or_else(|| env::var_os("XDG_CACHE_HOME"))
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(env::temp_dir);
+
+        return user_dir.join("rust3d").join("programs");
+    }
+
+    /// Key of a program built from `stages`, the name and preprocessed code of each stage
+    pub fn key(&self, stages: &[(&str, &str)]) -> u64 {
+        let mut hash = FNV_OFFSET;
+        hash = fnv1a(hash, self.driver.as_bytes());
+        for (name, code) in stages {
+            // Separators keep different splits of the same text apart
+            hash = fnv1a(hash, &[0]);
+            hash = fnv1a(hash, name.as_bytes());
+            hash = fnv1a(hash, &[0]);
+            hash = fnv1a(hash, code.as_bytes());
+        }
+
+        return hash;
+    }
+
+    /// Load the cached binary of the program `name` into `program` if it was stored
+    /// with `key`. Returns whether the program is now linked. A binary the driver
+    /// rejects (e.g. after a driver update that kept the version string) is deleted.
+    pub unsafe fn load(&self, name: &str, key: u64, program: GLuint) -> bool {
+        let path = self.path(name);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+
+        let (format, binary) = match decode(&data, key) {
+            CacheFile::Binary { format, binary } => (format, binary),
+            CacheFile::Stale => return false,
+            CacheFile::Invalid => {
+                self.remove(&path, "is not a program binary");
+                return false;
+            }
+        };
+
+        gl::ProgramBinary(
+            program,
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as GLsizei,
+        );
+
+        let mut success = gl::FALSE as GLint;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+        if success != gl::TRUE as GLint {
+            self.remove(&path, "was rejected by the driver");
+            return false;
+        }
+
+        return true;
+    }
+
+    /// Save the binary of a linked program as the binary of `name`, replacing the
+    /// one stored before. `glProgramParameteri` with `PROGRAM_BINARY_RETRIEVABLE_HINT`
+    /// should be set before linking. Failures are only logged since the program
+    /// itself is fine.
+    pub unsafe fn store(&self, name: &str, key: u64, program: GLuint) {
+        let mut length = 0;
+        gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length);
+        if length <= 0 {
+            return;
+        }
+
+        let mut binary = vec![0u8; length as usize];
+        let mut written = 0;
+        let mut format = 0;
+        gl::GetProgramBinary(
+            program,
+            length,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr() as *mut c_void,
+        );
+        binary.truncate(written as usize);
+
+        let path = self.path(name);
+        if let Err(e) = fs::write(&path, encode(key, format, &binary)) {
+            print_warning_log(&Error::io(&path, e).to_string());
+        }
+    }
+
+    /// File of the program `name`, which may contain characters not allowed in file names
+    fn path(&self, name: &str) -> PathBuf {
+        return self
+            .dir
+            .join(format!("{:016x}.bin", fnv1a(FNV_OFFSET, name.as_bytes())));
+    }
+
+    fn remove(&self, path: &Path, reason: &str) {
+        print_warning_log(&format!("Program binary {} {}", path.display(), reason));
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Contents of a cache file read by `decode`
+#[derive(Debug, PartialEq)]
+enum CacheFile<'a> {
+    /// Binary stored with the requested key
+    Binary { format: GLenum, binary: &'a [u8] },
+    /// Binary of other sources or another driver
+    Stale,
+    /// File not written by `store`, or cut short
+    Invalid,
+}
+
+/// Length of the key and the binary format after the magic
+const HEADER_LEN: usize = 12;
+
+fn encode(key: u64, format: GLenum, binary: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(MAGIC.len() + HEADER_LEN + binary.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&key.to_le_bytes());
+    data.extend_from_slice(&format.to_le_bytes());
+    data.extend_from_slice(binary);
+
+    return data;
+}
+
+fn decode(data: &[u8], key: u64) -> CacheFile<'_> {
+    let data = match data.strip_prefix(MAGIC) {
+        Some(data) if data.len() > HEADER_LEN => data,
+        _ => return CacheFile::Invalid,
+    };
+
+    let (stored_key, data) = data.split_at(8);
+    if u64::from_le_bytes(stored_key.try_into().unwrap()) != key {
+        return CacheFile::Stale;
+    }
+    let (format, binary) = data.split_at(4);
+
+    return CacheFile::Binary {
+        format: GLenum::from_le_bytes(format.try_into().unwrap()),
+        binary,
+    };
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same key in every build
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    return bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    });
+}
+
+unsafe fn gl_string(name: GLenum) -> String {
+    let string = gl::GetString(name);
+    if string.is_null() {
+        return String::new();
+    }
+
+    return CStr::from_ptr(string as *const GLchar)
+        .to_string_lossy()
+        .into_owned();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: u64 = 0x0123_4567_89ab_cdef;
+
+    #[test]
+    fn header_round_trips() {
+        let data = encode(KEY, 0x8741, &[1, 2, 3]);
+
+        assert_eq!(&data[..4], b"R3DP");
+        assert_eq!(
+            decode(&data, KEY),
+            CacheFile::Binary {
+                format: 0x8741,
+                binary: &[1, 2, 3]
+            }
+        );
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let data = encode(KEY, 0x8741, &[1, 2, 3]);
+
+        let mut wrong_magic = data.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(decode(&wrong_magic, KEY), CacheFile::Invalid);
+        assert_eq!(decode(&data, KEY + 1), CacheFile::Stale);
+        // Cut in the header, and right after it with no binary
+        assert_eq!(decode(&data[..10], KEY), CacheFile::Invalid);
+        assert_eq!(decode(&data[..16], KEY), CacheFile::Invalid);
+        assert_eq!(decode(&[], KEY), CacheFile::Invalid);
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(FNV_OFFSET, b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(FNV_OFFSET, b"foobar"), 0x8594_4171_f739_67e8);
+        // Hashing in parts is the same as hashing the whole
+        assert_eq!(
+            fnv1a(fnv1a(FNV_OFFSET, b"foo"), b"bar"),
+            fnv1a(FNV_OFFSET, b"foobar")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{Error, Result};
+
+/// Environment variable naming a resource root
+pub const ROOT_VAR: &str = "RUST3D_RESOURCE_ROOT";
+
+/// Directories relative paths are searched in, set by `set_roots`.
+/// Until then paths are relative to the working directory.
+static ROOTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Files compiled into the binary, keyed by their path relative to the repository.
+/// They are used when the file is not found under any root, so the built-in
+/// shaders and the sample model and texture work from any working directory.
+const EMBEDDED: &[(&str, &[u8])] = &[
+    (
+        "src/shader/Basic.vert",
+        include_str!("shader/Basic.vert").as_bytes(),
+    ),
+    (
+        "src/shader/Basic.frag",
+        include_str!("shader/Basic.frag").as_bytes(),
+    ),
+    (
+        "src/shader/Camera.glsl",
+        include_str!("shader/Camera.glsl").as_bytes(),
+    ),
+    (
+        "src/shader/Lighting.glsl",
+        include_str!("shader/Lighting.glsl").as_bytes(),
+    ),
+    (
+        "src/shader/Normals.vert",
+        include_str!("shader/Normals.vert").as_bytes(),
+    ),
+    (
+        "src/shader/Normals.geom",
+        include_str!("shader/Normals.geom").as_bytes(),
+    ),
+    (
+        "src/shader/Normals.frag",
+        include_str!("shader/Normals.frag").as_bytes(),
+    ),
+    (
+        "src/shader/Particles.vert",
+        include_str!("shader/Particles.vert").as_bytes(),
+    ),
+    (
+        "src/shader/Particles.frag",
+        include_str!("shader/Particles.frag").as_bytes(),
+    ),
+    (
+        "src/shader/Particles.comp",
+        include_str!("shader/Particles.comp").as_bytes(),
+    ),
+    (
+        "src/shader/Skybox.vert",
+        include_str!("shader/Skybox.vert").as_bytes(),
+    ),
+    (
+        "src/shader/Skybox.frag",
+        include_str!("shader/Skybox.frag").as_bytes(),
+    ),
+    ("resource/Cube.obj", include_bytes!("../resource/Cube.obj")),
+    (
+        "resource/Texture.png",
+        include_bytes!("../resource/Texture.png"),
+    ),
+];
+
+/// Search resources in `cli_root`, then `$RUST3D_RESOURCE_ROOT`, the working
+/// directory, the directory of the executable, and the repository when the
+/// executable is in its `target/<profile>`. The working directory comes before the
+/// executable so that relative paths given on the command line resolve as usual.
+/// Other ancestors of the executable are not searched.
+pub fn set_roots(cli_root: Option<PathBuf>) {
+    let mut roots: Vec<PathBuf> = cli_root.into_iter().collect();
+    if let Some(root) = env::var_os(ROOT_VAR) {
+        roots.push(PathBuf::from(root));
+    }
+    // Working directory
+    roots.push(PathBuf::new());
+    if let Ok(exe) = env::current_exe() {
+        if let Some(dir) = exe.parent() {
+            roots.push(dir.to_path_buf());
+            roots.extend(repository_of_build(dir));
+        }
+    }
+
+    if let Ok(mut current) = ROOTS.lock() {
+        *current = roots;
+    }
+}
+
+/// Path of the file in the first root that has it. Absolute paths are used as they are.
+pub fn find(path: &Path) -> Result<PathBuf> {
+    let roots = match ROOTS.lock() {
+        Ok(roots) if !roots.is_empty() => roots.clone(),
+        _ => vec![PathBuf::new()],
+    };
+
+    return roots
+        .iter()
+        .map(|root| root.join(path))
+        .find(|path| path.is_file())
+        .ok_or_else(|| Error::io(path, io::Error::from(io::ErrorKind::NotFound)));
+}
+
+/// Whether the file is found under a root or embedded
+pub fn exists(path: &Path) -> bool {
+    return find(path).is_ok() || embedded(path).is_some();
+}
+
+/// Contents of the file found under a root, or of the embedded copy
+pub fn read(path: &Path) -> Result<Cow<'static, [u8]>> {
+    let error = match find(path) {
+        Ok(file) => match fs::read(&file) {
+            Ok(data) => return Ok(Cow::Owned(data)),
+            Err(e) => Error::io(&file, e),
+        },
+        Err(error) => error,
+    };
+
+    return match embedded(path) {
+        Some(data) => Ok(Cow::Borrowed(data)),
+        None => Err(error),
+    };
+}
+
+pub fn read_to_string(path: &Path) -> Result<String> {
+    let data = read(path)?;
+
+    return String::from_utf8(data.into_owned()).map_err(|e| Error::Decode {
+        path: path.to_string_lossy().into_owned(),
+        message: e.to_string(),
+    });
+}
+
+/// Repository containing `exe_dir` when it is the `target/<profile>` of a cargo build
+fn repository_of_build(exe_dir: &Path) -> Option<PathBuf> {
+    let target = exe_dir.parent()?;
+    if target.file_name()? != "target" {
+        return None;
+    }
+
+    return target.parent().map(Path::to_path_buf);
+}
+
+/// Embedded file with the path, ignoring `.` and resolving `..`
+fn embedded(path: &Path) -> Option<&'static [u8]> {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_str()?),
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let name = components.join("/");
+
+    return EMBEDDED
+        .iter()
+        .find(|(embedded_name, _)| *embedded_name == name)
+        .map(|(_, data)| *data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_cargo_builds_search_the_repository() {
+        let repository = Path::new("/home/user/rust3d");
+        assert_eq!(
+            repository_of_build(&repository.join("target").join("release")),
+            Some(repository.to_path_buf())
+        );
+        assert_eq!(repository_of_build(Path::new("/opt/rust3d/bin")), None);
+        assert_eq!(repository_of_build(Path::new("/")), None);
+    }
+}
@@ -0,0 +1,135 @@
+use gl::types::*;
+
+use crate::common::{gl_has_extension, gl_supports};
+use crate::gl_object::Sampler;
+
+// From GL_EXT_texture_filter_anisotropic, core in OpenGL 4.6
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    fn gl_mode(self) -> GLenum {
+        return match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+/// How a texture is sampled. Applied to the texture itself by `TextureLoader`,
+/// or to a sampler object shared by several textures with `create_sampler`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerDesc {
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    /// Third coordinate of 3D textures and cubemaps
+    pub wrap_r: Wrap,
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    /// Filter between mipmap levels, or `None` to only read the base level.
+    /// `Some(Filter::Linear)` with linear min filtering is trilinear filtering.
+    pub mipmap_filter: Option<Filter>,
+    /// Maximum anisotropy, 1 to disable. Clamped to what the driver supports and
+    /// ignored without anisotropic filtering support.
+    pub anisotropy: f32,
+}
+
+impl Default for SamplerDesc {
+    /// Repeating trilinear filtering
+    fn default() -> SamplerDesc {
+        return SamplerDesc {
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            wrap_r: Wrap::Repeat,
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap_filter: Some(Filter::Linear),
+            anisotropy: 1.0,
+        };
+    }
+}
+
+impl SamplerDesc {
+    /// Sampler object with these parameters. Bind it with `gl::BindSampler(unit, sampler.id())`
+    /// to override the parameters of the texture bound to that unit.
+    pub fn create_sampler(&self, label: &str) -> Sampler {
+        let sampler = Sampler::new(label);
+        let id = sampler.id();
+
+        unsafe {
+            self.apply(
+                |name, value| gl::SamplerParameteri(id, name, value),
+                |name, value| gl::SamplerParameterf(id, name, value),
+            );
+        }
+
+        return sampler;
+    }
+
+    /// Set the parameters of the texture bound to `target`
+    pub unsafe fn apply_to_texture(&self, target: GLenum) {
+        self.apply(
+            |name, value| gl::TexParameteri(target, name, value),
+            |name, value| gl::TexParameterf(target, name, value),
+        );
+    }
+
+    /// Same desc without mipmap filtering, for textures that have no mipmaps
+    pub fn without_mipmaps(&self) -> SamplerDesc {
+        return SamplerDesc {
+            mipmap_filter: None,
+            ..*self
+        };
+    }
+
+    unsafe fn apply(&self, set_int: impl Fn(GLenum, GLint), set_float: impl Fn(GLenum, GLfloat)) {
+        set_int(gl::TEXTURE_WRAP_S, self.wrap_s.gl_mode() as GLint);
+        set_int(gl::TEXTURE_WRAP_T, self.wrap_t.gl_mode() as GLint);
+        set_int(gl::TEXTURE_WRAP_R, self.wrap_r.gl_mode() as GLint);
+        set_int(gl::TEXTURE_MIN_FILTER, self.gl_min_filter() as GLint);
+        set_int(
+            gl::TEXTURE_MAG_FILTER,
+            match self.mag_filter {
+                Filter::Nearest => gl::NEAREST,
+                Filter::Linear => gl::LINEAR,
+            } as GLint,
+        );
+
+        if self.anisotropy > 1.0 && supports_anisotropy() {
+            let mut max_anisotropy = 1.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            set_float(TEXTURE_MAX_ANISOTROPY, self.anisotropy.min(max_anisotropy));
+        }
+    }
+
+    fn gl_min_filter(&self) -> GLenum {
+        return match (self.min_filter, self.mipmap_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+    }
+}
+
+/// Whether the context has anisotropic filtering
+pub fn supports_anisotropy() -> bool {
+    return gl_supports((4, 6), "GL_ARB_texture_filter_anisotropic")
+        || gl_has_extension("GL_EXT_texture_filter_anisotropic");
+}
@@ -1,144 +1,987 @@
 extern crate gl;
 
-use cgmath::Array;
-use cgmath::Matrix;
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
 use gl::types::*;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::path::Path;
 use std::ptr;
-use std::str;
-use std::vec::Vec;
+use std::rc::Rc;
+use std::slice;
+use std::time::SystemTime;
 
-pub struct Shader {
-    id: u32,
+use crate::common::print_warning_log;
+use crate::error::{Error, Result};
+use crate::gl_object::Program;
+use crate::preprocessor::preprocess;
+use crate::program_cache::ProgramCache;
+use crate::resources;
+use crate::uniform_buffer::{Std140, UniformBuffer};
+
+/// Value that can be stored in a uniform of type `GL_TYPE`
+pub trait UniformValue: Sized {
+    const GL_TYPE: GLenum;
+
+    /// Upload `values` to consecutive array elements starting at `location`
+    unsafe fn set(location: i32, values: &[Self]);
 }
 
-impl Shader {
-    pub fn new(vertex_path: &str, frag_path: &str) -> Shader {
-        let mut shader = Shader { id: 0 };
-
-        // Load vertex shader
-        let mut vertex_file = File::open(vertex_path)
-            .unwrap_or_else(|_| panic!("Failed to load vertex shader file: {}", vertex_path));
-        let mut vertex_code = String::new();
-        vertex_file
-            .read_to_string(&mut vertex_code)
-            .expect("Failed to read vertex shader file");
-
-        // Load frag shader
-        let mut frag_file = File::open(frag_path)
-            .unwrap_or_else(|_| panic!("Failed to load frag shader file: {}", frag_path));
-        let mut frag_code = String::new();
-        frag_file
-            .read_to_string(&mut frag_code)
-            .expect("Failed to read frag shader file");
-
-        // Create cstring version shader code
-        let vertex_code_cstr = match CString::new(vertex_code.as_bytes()) {
-            Ok(cstr) => cstr,
-            Err(e) => panic!("Failed to create cstr vertex shader code: {:?}", e),
+macro_rules! impl_uniform_value {
+    ($($type:ty => $gl_type:expr, |$location:ident, $count:ident, $pointer:ident| $set:expr),*) => {
+        $(
+            impl UniformValue for $type {
+                const GL_TYPE: GLenum = $gl_type;
+
+                unsafe fn set(location: i32, values: &[Self]) {
+                    let $location = location;
+                    let $count = values.len() as GLsizei;
+                    let $pointer = values.as_ptr() as *const _;
+                    $set;
+                }
+            }
+        )*
+    };
+}
+
+impl_uniform_value!(
+    i32 => gl::INT, |l, n, p| gl::Uniform1iv(l, n, p),
+    u32 => gl::UNSIGNED_INT, |l, n, p| gl::Uniform1uiv(l, n, p),
+    f32 => gl::FLOAT, |l, n, p| gl::Uniform1fv(l, n, p),
+    [f32; 2] => gl::FLOAT_VEC2, |l, n, p| gl::Uniform2fv(l, n, p),
+    [f32; 3] => gl::FLOAT_VEC3, |l, n, p| gl::Uniform3fv(l, n, p),
+    [f32; 4] => gl::FLOAT_VEC4, |l, n, p| gl::Uniform4fv(l, n, p),
+    [i32; 2] => gl::INT_VEC2, |l, n, p| gl::Uniform2iv(l, n, p),
+    [i32; 3] => gl::INT_VEC3, |l, n, p| gl::Uniform3iv(l, n, p),
+    [i32; 4] => gl::INT_VEC4, |l, n, p| gl::Uniform4iv(l, n, p),
+    Vector2<f32> => gl::FLOAT_VEC2, |l, n, p| gl::Uniform2fv(l, n, p),
+    Vector3<f32> => gl::FLOAT_VEC3, |l, n, p| gl::Uniform3fv(l, n, p),
+    Vector4<f32> => gl::FLOAT_VEC4, |l, n, p| gl::Uniform4fv(l, n, p),
+    Matrix3<f32> => gl::FLOAT_MAT3, |l, n, p| gl::UniformMatrix3fv(l, n, gl::FALSE, p),
+    Matrix4<f32> => gl::FLOAT_MAT4, |l, n, p| gl::UniformMatrix4fv(l, n, gl::FALSE, p)
+);
+
+impl UniformValue for bool {
+    const GL_TYPE: GLenum = gl::BOOL;
+
+    unsafe fn set(location: i32, values: &[Self]) {
+        let values: Vec<i32> = values.iter().map(|&value| value as i32).collect();
+        gl::Uniform1iv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+
+/// Rust struct mirroring a GLSL struct. Usually derived with `#[derive(UniformStruct)]`,
+/// which sets each field as `<name>.<field>` (or `#[uniform(name = "...")]`).
+pub trait UniformStruct {
+    /// GLSL member names, in the order the fields are set
+    const MEMBERS: &'static [&'static str];
+
+    unsafe fn set_fields(&self, shader: &Shader, name: &str);
+}
+
+/// Active uniform of a linked program. Arrays are stored under their name without `[0]`.
+pub struct UniformInfo {
+    pub name: String,
+    pub gl_type: GLenum,
+    /// Number of array elements (1 if not an array)
+    pub size: i32,
+    pub location: i32,
+}
+
+/// Active vertex attribute of a linked program
+pub struct AttributeInfo {
+    pub name: String,
+    pub gl_type: GLenum,
+    pub size: i32,
+    pub location: i32,
+}
+
+/// Active uniform block of a linked program
+pub struct UniformBlockInfo {
+    pub name: String,
+    pub index: u32,
+    /// Minimum buffer size in bytes (`GL_UNIFORM_BLOCK_DATA_SIZE`)
+    pub size: i32,
+}
+
+/// File the program was built from, with its modification time at load
+struct SourceFile {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl SourceFile {
+    fn new(path: &str) -> SourceFile {
+        return SourceFile {
+            path: path.to_string(),
+            modified: modified_time(path),
         };
-        let frag_code_cstr = match CString::new(frag_code.as_bytes()) {
-            Ok(cstr) => cstr,
-            Err(e) => panic!("Failed to create cstr frag shader code: {:?}", e),
+    }
+}
+
+/// Programmable pipeline stage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+    /// Needs OpenGL 4.3, see `ComputeShader`
+    Compute,
+}
+
+impl ShaderStage {
+    pub fn gl_type(self) -> GLenum {
+        return match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+            ShaderStage::Compute => gl::COMPUTE_SHADER,
+        };
+    }
+
+    /// Name used in error messages
+    pub fn name(self) -> &'static str {
+        return match self {
+            ShaderStage::Vertex => "VERTEX",
+            ShaderStage::TessControl => "TESS_CONTROL",
+            ShaderStage::TessEvaluation => "TESS_EVALUATION",
+            ShaderStage::Geometry => "GEOMETRY",
+            ShaderStage::Fragment => "FRAGMENT",
+            ShaderStage::Compute => "COMPUTE",
+        };
+    }
+}
+
+/// Stage files and defines of a program. Setting a stage twice replaces the file.
+#[derive(Clone, Default)]
+pub struct ShaderBuilder {
+    /// Stages in pipeline order
+    stages: Vec<(ShaderStage, String)>,
+    /// `#define`s injected after `#version` in every stage
+    defines: Vec<(String, String)>,
+    program_cache: Option<Rc<ProgramCache>>,
+}
+
+impl ShaderBuilder {
+    pub fn vertex(self, path: &str) -> ShaderBuilder {
+        return self.stage(ShaderStage::Vertex, path);
+    }
+
+    pub fn geometry(self, path: &str) -> ShaderBuilder {
+        return self.stage(ShaderStage::Geometry, path);
+    }
+
+    pub fn fragment(self, path: &str) -> ShaderBuilder {
+        return self.stage(ShaderStage::Fragment, path);
+    }
+
+    /// Compute programs cannot have other stages. Prefer `ComputeShader`.
+    pub fn compute(self, path: &str) -> ShaderBuilder {
+        return self.stage(ShaderStage::Compute, path);
+    }
+
+    pub fn stage(mut self, stage: ShaderStage, path: &str) -> ShaderBuilder {
+        self.stages.retain(|(other, _)| *other != stage);
+        self.stages.push((stage, path.to_string()));
+        self.stages.sort_by_key(|(stage, _)| *stage as u32);
+
+        return self;
+    }
+
+    pub fn define(mut self, name: &str, value: &str) -> ShaderBuilder {
+        self.defines.push((name.to_string(), value.to_string()));
+
+        return self;
+    }
+
+    pub fn defines(self, defines: &[(&str, &str)]) -> ShaderBuilder {
+        return defines
+            .iter()
+            .fold(self, |builder, (name, value)| builder.define(name, value));
+    }
+
+    /// Load the linked program from `cache` if it was built from the same sources
+    /// before, and store it there after linking
+    pub fn program_cache(mut self, cache: &Rc<ProgramCache>) -> ShaderBuilder {
+        self.program_cache = Some(Rc::clone(cache));
+
+        return self;
+    }
+
+    /// Compile every stage and link them into a program
+    pub fn build(&self) -> Result<Shader> {
+        self.check_stages()?;
+
+        // Expand includes and defines
+        let mut stage_sources = Vec::with_capacity(self.stages.len());
+        for (stage, path) in &self.stages {
+            stage_sources.push((*stage, path, preprocess(path, &self.defines)?));
+        }
+
+        // Watch every file any stage was built from
+        let mut sources: Vec<SourceFile> = Vec::new();
+        for (_, _, source) in &stage_sources {
+            for file in &source.files {
+                if !sources.iter().any(|source| &source.path == file) {
+                    sources.push(SourceFile::new(file));
+                }
+            }
+        }
+
+        let paths: Vec<&str> = self.stages.iter().map(|(_, path)| path.as_str()).collect();
+        let name = paths.join(" + ");
+
+        // Each set of stage files and defines has one cache file
+        let cache_entry = self.program_cache.as_ref().map(|cache| {
+            let mut cache_name = name.clone();
+            for (define, value) in &self.defines {
+                cache_name.push_str(&format!(" {}={}", define, value));
+            }
+            let stages: Vec<(&str, &str)> = stage_sources
+                .iter()
+                .map(|(stage, _, source)| (stage.name(), source.code.as_str()))
+                .collect();
+            (cache_name, cache.key(&stages))
+        });
+        let mut shader = Shader {
+            program: Program::new(&name),
+            name,
+            builder: self.clone(),
+            sources,
+            block_bindings: RefCell::new(HashMap::new()),
+            uniforms: HashMap::new(),
+            uniform_blocks: HashMap::new(),
+            attributes: Vec::new(),
+            warned: RefCell::new(HashSet::new()),
         };
 
         unsafe {
-            // Create vertex shader
-            let vertex = gl::CreateShader(gl::VERTEX_SHADER);
-            gl::ShaderSource(vertex, 1, &vertex_code_cstr.as_ptr(), ptr::null());
-            gl::CompileShader(vertex);
-            shader.check_compile_error(vertex, "VERTEX");
-
-            // Create frag shader
-            let frag = gl::CreateShader(gl::FRAGMENT_SHADER);
-            gl::ShaderSource(frag, 1, &frag_code_cstr.as_ptr(), ptr::null());
-            gl::CompileShader(frag);
-            shader.check_compile_error(frag, "FRAGMENT");
-
-            // Attach shader to program
-            let id = gl::CreateProgram();
-            gl::AttachShader(id, vertex);
-            gl::AttachShader(id, frag);
+            let id = shader.program.id();
+            if let (Some(cache), Some((cache_name, key))) = (&self.program_cache, &cache_entry) {
+                if cache.load(cache_name, *key, id) {
+                    shader.reflect();
+                    return Ok(shader);
+                }
+                gl::ProgramParameteri(id, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint);
+            }
+
+            // Compile and attach each stage
+            let mut stage_objects = Vec::with_capacity(stage_sources.len());
+            for (stage, path, source) in stage_sources {
+                let result = to_cstring(source.code, path)
+                    .and_then(|code| compile_stage(stage, &code, &source.files));
+
+                match result {
+                    Ok(object) => {
+                        gl::AttachShader(id, object);
+                        stage_objects.push(object);
+                    }
+                    Err(e) => {
+                        delete_stages(id, &stage_objects);
+                        return Err(e);
+                    }
+                }
+            }
+
             gl::LinkProgram(id);
-            shader.check_compile_error(id, "PROGRAM");
+            let result = shader.check_link_error();
 
             // Delete shaders that have been used up
-            gl::DeleteShader(vertex);
-            gl::DeleteShader(frag);
+            delete_stages(id, &stage_objects);
+
+            result?;
+
+            if let (Some(cache), Some((cache_name, key))) = (&self.program_cache, &cache_entry) {
+                cache.store(cache_name, *key, id);
+            }
+
+            shader.reflect();
+        }
+
+        return Ok(shader);
+    }
+
+    /// Reject stage combinations that can never link, before compiling anything
+    fn check_stages(&self) -> Result<()> {
+        let has = |stage: ShaderStage| self.stages.iter().any(|(other, _)| *other == stage);
+
+        let message = if self.stages.is_empty() {
+            "program has no stages"
+        } else if has(ShaderStage::Compute) {
+            if self.stages.len() == 1 {
+                return Ok(());
+            }
+            "compute stage cannot be linked with other stages"
+        } else if !has(ShaderStage::Vertex) {
+            "program has no vertex stage"
+        } else if has(ShaderStage::TessControl) && !has(ShaderStage::TessEvaluation) {
+            "tessellation control stage needs a tessellation evaluation stage"
+        } else {
+            return Ok(());
+        };
+
+        let paths: Vec<&str> = self.stages.iter().map(|(_, path)| path.as_str()).collect();
+        return Err(Error::Link {
+            program: paths.join(" + "),
+            log: message.to_string(),
+        });
+    }
+}
 
-            shader.id = id;
+pub struct Shader {
+    program: Program,
+    /// Source file names, used in error messages
+    name: String,
+    /// Stages and defines, used again by `reload_if_modified`
+    builder: ShaderBuilder,
+    /// Files watched by `reload_if_modified`
+    sources: Vec<SourceFile>,
+    /// Uniform block bindings, applied again when the program is reloaded
+    block_bindings: RefCell<HashMap<String, u32>>,
+    uniforms: HashMap<String, UniformInfo>,
+    uniform_blocks: HashMap<String, UniformBlockInfo>,
+    attributes: Vec<AttributeInfo>,
+    /// Uniform names that have already been warned about
+    warned: RefCell<HashSet<String>>,
+}
+
+impl Shader {
+    /// Program with any combination of stages
+    pub fn builder() -> ShaderBuilder {
+        return ShaderBuilder::default();
+    }
+
+    /// Rebuild the program if a source file has been modified since it was loaded.
+    /// Returns whether the program was replaced. If the new program fails to compile
+    /// or link, the current one is kept and the error is returned.
+    pub fn reload_if_modified(&mut self) -> Result<bool> {
+        let mut modified = false;
+        for source in &mut self.sources {
+            let time = modified_time(&source.path);
+            if time != source.modified {
+                source.modified = time;
+                modified = true;
+            }
         }
 
-        return shader;
+        if !modified {
+            return Ok(false);
+        }
+
+        let shader = self.builder.build()?;
+
+        // Uniform block bindings are stored in the program object
+        for (name, binding) in self.block_bindings.borrow().iter() {
+            unsafe {
+                shader.bind_uniform_block(name, *binding);
+            }
+        }
+
+        *self = shader;
+
+        return Ok(true);
     }
 
     pub unsafe fn use_program(&self) {
-        gl::UseProgram(self.id);
+        gl::UseProgram(self.program.id());
+    }
+
+    /// GL name of the program
+    pub fn id(&self) -> u32 {
+        return self.program.id();
     }
 
     pub unsafe fn set_float(&self, name: &CStr, value: f32) {
-        gl::Uniform1f(gl::GetUniformLocation(self.id, name.as_ptr()), value);
+        self.set_uniform(name, &value);
     }
 
-    pub unsafe fn set_mat(&self, name: &CStr, mat: &cgmath::Matrix4<f32>) {
-        gl::UniformMatrix4fv(
-            gl::GetUniformLocation(self.id, name.as_ptr()),
-            1,
-            gl::FALSE,
-            mat.as_ptr(),
-        );
+    pub unsafe fn set_mat(&self, name: &CStr, mat: &Matrix4<f32>) {
+        self.set_uniform(name, mat);
     }
 
-    pub unsafe fn set_vec(&self, name: &CStr, value: &cgmath::Vector3<f32>) {
-        gl::Uniform3fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, value.as_ptr());
+    /// Make the sampler read from texture unit `unit` (`gl::TEXTURE0 + unit`)
+    pub unsafe fn set_sampler(&self, name: &CStr, unit: u32) {
+        self.set_uniform(name, &(unit as i32));
     }
 
-    unsafe fn check_compile_error(&self, shader: u32, type_: &str) {
-        let mut success = gl::FALSE as GLint;
-        let mut info_log = Vec::<u8>::with_capacity(2048);
-        info_log.set_len(1024 - 1);
-
-        if type_ != "PROGRAM" {
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-
-            if success != gl::TRUE as GLint {
-                gl::GetShaderInfoLog(
-                    shader,
-                    1024,
-                    ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
+    pub unsafe fn set_uniform<T: UniformValue>(&self, name: &CStr, value: &T) {
+        self.set_uniform_array(name, slice::from_ref(value));
+    }
+
+    /// Set consecutive array elements starting at `name` (`uWeights` or `uWeights[0]`)
+    pub unsafe fn set_uniform_array<T: UniformValue>(&self, name: &CStr, values: &[T]) {
+        self.set_uniform_str(&name.to_string_lossy(), values);
+    }
+
+    /// Set every field of a GLSL struct uniform
+    pub unsafe fn set_struct<T: UniformStruct>(&self, name: &CStr, value: &T) {
+        value.set_fields(self, &name.to_string_lossy());
+    }
+
+    /// Same as `set_uniform_array` with a Rust string name, used by `UniformStruct`
+    pub unsafe fn set_uniform_str<T: UniformValue>(&self, name: &str, values: &[T]) {
+        let info = match self.checked_uniform(name, T::GL_TYPE) {
+            Some(info) => info,
+            None => return,
+        };
+
+        let mut values = values;
+        if values.len() > info.size as usize {
+            self.warn_once(
+                name,
+                &format!(
+                    "Uniform {} in {} has {} elements, but {} were set",
+                    name,
+                    self.name,
+                    info.size,
+                    values.len()
+                ),
+            );
+            values = &values[..info.size as usize];
+        }
+
+        T::set(info.location, values);
+    }
+
+    /// Active uniform with the name, if any
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        return self
+            .uniforms
+            .get(name)
+            .or_else(|| self.uniforms.get(name.strip_suffix("[0]")?));
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = &UniformInfo> {
+        return self.uniforms.values();
+    }
+
+    pub fn attributes(&self) -> &[AttributeInfo] {
+        return &self.attributes;
+    }
 
-                println!(
-                    "Failed to compile shader: type = {}, log = {}",
-                    type_,
-                    str::from_utf8(&info_log).unwrap()
+    pub fn uniform_blocks(&self) -> impl Iterator<Item = &UniformBlockInfo> {
+        return self.uniform_blocks.values();
+    }
+
+    /// Make the uniform block `name` read from the binding point of `buffer`.
+    /// Warns once if the block is not active or is larger than `T`.
+    pub unsafe fn set_uniform_block<T: Std140>(&self, name: &CStr, buffer: &UniformBuffer<T>) {
+        let name = name.to_string_lossy();
+
+        let info = match self.bind_uniform_block(&name, buffer.binding()) {
+            Some(info) => info,
+            None => {
+                self.warn_once(
+                    &name,
+                    &format!("Uniform block {} is not active in {}", name, self.name),
                 );
-            } else {
-                gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-
-                if success != gl::TRUE as GLint {
-                    gl::GetProgramInfoLog(
-                        shader,
-                        1024,
-                        ptr::null_mut(),
-                        info_log.as_mut_ptr() as *mut GLchar,
-                    );
-
-                    let info_log_string = match String::from_utf8(info_log) {
-                        Ok(log) => log,
-                        Err(vec) => panic!("Failed to convert to log from buffer: {}", vec),
-                    };
-
-                    println!(
-                        "Failed to link shader: type = {}, log = {}",
-                        type_, info_log_string
-                    );
-                }
+                return;
             }
+        };
+
+        if info.size as usize > T::SIZE {
+            self.warn_once(
+                &name,
+                &format!(
+                    "Uniform block {} in {} needs {} bytes, but the buffer has {}",
+                    name,
+                    self.name,
+                    info.size,
+                    T::SIZE
+                ),
+            );
+        }
+    }
+
+    /// Remember the binding of the block and set it if the block is active.
+    /// Unlike `set_uniform_block`, this does not warn or check the buffer size.
+    pub unsafe fn bind_uniform_block(&self, name: &str, binding: u32) -> Option<&UniformBlockInfo> {
+        self.block_bindings
+            .borrow_mut()
+            .insert(name.to_string(), binding);
+
+        let info = self.uniform_blocks.get(name)?;
+        gl::UniformBlockBinding(self.program.id(), info.index, binding);
+
+        return Some(info);
+    }
+
+    /// Cached uniform info. Warns once and returns `None` if the uniform
+    /// is not active or its type does not match `gl_type`.
+    fn checked_uniform(&self, name: &str, gl_type: GLenum) -> Option<&UniformInfo> {
+        match self.uniform(name) {
+            Some(info) if type_matches(gl_type, info.gl_type) => return Some(info),
+            Some(info) => self.warn_once(
+                name,
+                &format!(
+                    "Uniform {} in {} has type 0x{:04X}, but was set as 0x{:04X}",
+                    name, self.name, info.gl_type, gl_type
+                ),
+            ),
+            None => self.warn_once(
+                name,
+                &format!("Uniform {} is not active in {}", name, self.name),
+            ),
         }
+
+        return None;
+    }
+
+    fn warn_once(&self, name: &str, message: &str) {
+        if self.warned.borrow_mut().insert(name.to_string()) {
+            print_warning_log(message);
+        }
+    }
+
+    /// Enumerate active uniforms and attributes of the linked program
+    unsafe fn reflect(&mut self) {
+        let id = self.program.id();
+
+        let mut count = 0;
+        let mut max_length = 0;
+        gl::GetProgramiv(id, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+        for index in 0..count as u32 {
+            let (name, gl_type, size) = active_variable(max_length, |length, size, type_, name| {
+                gl::GetActiveUniform(id, index, max_length, length, size, type_, name)
+            });
+
+            // Members of uniform blocks have no location
+            let location = match CString::new(name.as_str()) {
+                Ok(name) => gl::GetUniformLocation(id, name.as_ptr()),
+                Err(_) => -1,
+            };
+            if location < 0 {
+                continue;
+            }
+
+            let name = name.strip_suffix("[0]").unwrap_or(&name).to_string();
+            self.uniforms.insert(
+                name.clone(),
+                UniformInfo {
+                    name,
+                    gl_type,
+                    size,
+                    location,
+                },
+            );
+        }
+
+        gl::GetProgramiv(id, gl::ACTIVE_ATTRIBUTES, &mut count);
+        gl::GetProgramiv(id, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+        for index in 0..count as u32 {
+            let (name, gl_type, size) = active_variable(max_length, |length, size, type_, name| {
+                gl::GetActiveAttrib(id, index, max_length, length, size, type_, name)
+            });
+
+            let location = match CString::new(name.as_str()) {
+                Ok(name) => gl::GetAttribLocation(id, name.as_ptr()),
+                Err(_) => -1,
+            };
+
+            self.attributes.push(AttributeInfo {
+                name,
+                gl_type,
+                size,
+                location,
+            });
+        }
+        self.attributes.sort_by_key(|attribute| attribute.location);
+
+        gl::GetProgramiv(id, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+        gl::GetProgramiv(
+            id,
+            gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH,
+            &mut max_length,
+        );
+        for index in 0..count as u32 {
+            let mut name = vec![0u8; max_length.max(1) as usize];
+            let mut length = 0;
+            gl::GetActiveUniformBlockName(
+                id,
+                index,
+                max_length,
+                &mut length,
+                name.as_mut_ptr() as *mut GLchar,
+            );
+            name.truncate(length as usize);
+            let name = String::from_utf8_lossy(&name).into_owned();
+
+            let mut size = 0;
+            gl::GetActiveUniformBlockiv(id, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+
+            self.uniform_blocks
+                .insert(name.clone(), UniformBlockInfo { name, index, size });
+        }
+    }
+
+    unsafe fn check_link_error(&self) -> Result<()> {
+        let id = self.program.id();
+        let mut success = gl::FALSE as GLint;
+        gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
+
+        if success != gl::TRUE as GLint {
+            return Err(Error::Link {
+                program: self.name.clone(),
+                log: program_info_log(id),
+            });
+        }
+
+        return Ok(());
+    }
+}
+
+/// Query name, type and array size of an active uniform or attribute with
+/// `glGetActiveUniform`/`glGetActiveAttrib`
+unsafe fn active_variable(
+    max_length: GLint,
+    get: impl Fn(*mut GLsizei, *mut GLint, *mut GLenum, *mut GLchar),
+) -> (String, GLenum, i32) {
+    let mut name = vec![0u8; max_length.max(1) as usize];
+    let mut length = 0;
+    let mut size = 0;
+    let mut gl_type = 0;
+    get(
+        &mut length,
+        &mut size,
+        &mut gl_type,
+        name.as_mut_ptr() as *mut GLchar,
+    );
+    name.truncate(length as usize);
+
+    return (String::from_utf8_lossy(&name).into_owned(), gl_type, size);
+}
+
+/// Whether a value set as `expected` can be stored in a uniform of type `actual`.
+/// Bools accept ints and floats, and samplers and images are set with ints.
+fn type_matches(expected: GLenum, actual: GLenum) -> bool {
+    return expected == actual
+        || (actual == gl::BOOL && (expected == gl::INT || expected == gl::FLOAT))
+        || (expected == gl::INT && (is_sampler(actual) || is_image(actual)));
+}
+
+fn is_sampler(gl_type: GLenum) -> bool {
+    return matches!(
+        gl_type,
+        gl::SAMPLER_1D
+            | gl::SAMPLER_2D
+            | gl::SAMPLER_3D
+            | gl::SAMPLER_CUBE
+            | gl::SAMPLER_1D_SHADOW
+            | gl::SAMPLER_2D_SHADOW
+            | gl::SAMPLER_CUBE_SHADOW
+            | gl::SAMPLER_1D_ARRAY
+            | gl::SAMPLER_2D_ARRAY
+            | gl::SAMPLER_1D_ARRAY_SHADOW
+            | gl::SAMPLER_2D_ARRAY_SHADOW
+            | gl::SAMPLER_CUBE_MAP_ARRAY
+            | gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW
+            | gl::SAMPLER_2D_MULTISAMPLE
+            | gl::SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::SAMPLER_2D_RECT
+            | gl::SAMPLER_2D_RECT_SHADOW
+            | gl::SAMPLER_BUFFER
+            | gl::INT_SAMPLER_1D
+            | gl::INT_SAMPLER_2D
+            | gl::INT_SAMPLER_3D
+            | gl::INT_SAMPLER_CUBE
+            | gl::INT_SAMPLER_1D_ARRAY
+            | gl::INT_SAMPLER_2D_ARRAY
+            | gl::INT_SAMPLER_CUBE_MAP_ARRAY
+            | gl::INT_SAMPLER_2D_MULTISAMPLE
+            | gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::INT_SAMPLER_2D_RECT
+            | gl::INT_SAMPLER_BUFFER
+            | gl::UNSIGNED_INT_SAMPLER_1D
+            | gl::UNSIGNED_INT_SAMPLER_2D
+            | gl::UNSIGNED_INT_SAMPLER_3D
+            | gl::UNSIGNED_INT_SAMPLER_CUBE
+            | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_CUBE_MAP_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE
+            | gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_RECT
+            | gl::UNSIGNED_INT_SAMPLER_BUFFER
+    );
+}
+
+fn is_image(gl_type: GLenum) -> bool {
+    return matches!(
+        gl_type,
+        gl::IMAGE_1D
+            | gl::IMAGE_2D
+            | gl::IMAGE_3D
+            | gl::IMAGE_CUBE
+            | gl::IMAGE_1D_ARRAY
+            | gl::IMAGE_2D_ARRAY
+            | gl::IMAGE_CUBE_MAP_ARRAY
+            | gl::IMAGE_2D_MULTISAMPLE
+            | gl::IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::IMAGE_2D_RECT
+            | gl::IMAGE_BUFFER
+            | gl::INT_IMAGE_1D
+            | gl::INT_IMAGE_2D
+            | gl::INT_IMAGE_3D
+            | gl::INT_IMAGE_CUBE
+            | gl::INT_IMAGE_1D_ARRAY
+            | gl::INT_IMAGE_2D_ARRAY
+            | gl::INT_IMAGE_CUBE_MAP_ARRAY
+            | gl::INT_IMAGE_2D_MULTISAMPLE
+            | gl::INT_IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::INT_IMAGE_2D_RECT
+            | gl::INT_IMAGE_BUFFER
+            | gl::UNSIGNED_INT_IMAGE_1D
+            | gl::UNSIGNED_INT_IMAGE_2D
+            | gl::UNSIGNED_INT_IMAGE_3D
+            | gl::UNSIGNED_INT_IMAGE_CUBE
+            | gl::UNSIGNED_INT_IMAGE_1D_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_2D_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_CUBE_MAP_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE
+            | gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE_ARRAY
+            | gl::UNSIGNED_INT_IMAGE_2D_RECT
+            | gl::UNSIGNED_INT_IMAGE_BUFFER
+    );
+}
+
+/// Compile one shader stage. The shader object is deleted if compilation fails.
+/// `files` are the files of the source string numbers in the code, the first being the stage file.
+unsafe fn compile_stage(stage: ShaderStage, code: &CStr, files: &[String]) -> Result<u32> {
+    let shader = gl::CreateShader(stage.gl_type());
+    gl::ShaderSource(shader, 1, &code.as_ptr(), ptr::null());
+    gl::CompileShader(shader);
+
+    let mut success = gl::FALSE as GLint;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+
+    if success != gl::TRUE as GLint {
+        let log = shader_info_log(shader);
+        gl::DeleteShader(shader);
+
+        return Err(Error::Compile {
+            stage: stage.name(),
+            path: files[0].clone(),
+            log: map_log_lines(&log, files),
+        });
+    }
+
+    return Ok(shader);
+}
+
+unsafe fn delete_stages(program: u32, stage_objects: &[u32]) {
+    for &object in stage_objects {
+        gl::DetachShader(program, object);
+        gl::DeleteShader(object);
+    }
+}
+
+unsafe fn shader_info_log(shader: u32) -> String {
+    let mut length = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+
+    let mut info_log = vec![0u8; length.max(1) as usize];
+    let mut written = 0;
+    gl::GetShaderInfoLog(
+        shader,
+        info_log.len() as GLsizei,
+        &mut written,
+        info_log.as_mut_ptr() as *mut GLchar,
+    );
+    info_log.truncate(written as usize);
+
+    return String::from_utf8_lossy(&info_log).into_owned();
+}
+
+unsafe fn program_info_log(program: u32) -> String {
+    let mut length = 0;
+    gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+
+    let mut info_log = vec![0u8; length.max(1) as usize];
+    let mut written = 0;
+    gl::GetProgramInfoLog(
+        program,
+        info_log.len() as GLsizei,
+        &mut written,
+        info_log.as_mut_ptr() as *mut GLchar,
+    );
+    info_log.truncate(written as usize);
+
+    return String::from_utf8_lossy(&info_log).into_owned();
+}
+
+/// Replace the source string number in driver log lines with the file name.
+/// `files[n]` is the file of source string `n`. Handles the common formats
+/// `0:12: ...` (Mesa, AMD, Intel, optionally after `ERROR: `), `0:12(5): ...` (Mesa)
+/// and `0(12) : ...` (NVIDIA).
+fn map_log_lines(log: &str, files: &[String]) -> String {
+    let mut mapped = String::with_capacity(log.len());
+
+    for line in log.lines() {
+        // Skip a severity prefix such as `ERROR: `
+        let location_start = match line.find(": ") {
+            Some(index) if line[..index].chars().all(|c| c.is_ascii_alphabetic()) => index + 2,
+            _ => 0,
+        };
+
+        match parse_log_location(&line[location_start..]) {
+            Some((source, line_number, rest)) if source < files.len() => {
+                mapped.push_str(&line[..location_start]);
+                mapped.push_str(&format!("{}:{}", files[source], line_number));
+                mapped.push_str(rest);
+            }
+            _ => mapped.push_str(line),
+        }
+        mapped.push('\n');
+    }
+
+    return mapped;
+}
+
+/// Parse `source:line` or `source(line)` at the start of `text`.
+/// Returns source string number, line number and the remaining text.
+fn parse_log_location(text: &str) -> Option<(usize, usize, &str)> {
+    let source_end = text.find(|c: char| !c.is_ascii_digit())?;
+    let source = text[..source_end].parse().ok()?;
+
+    let (close, rest) = match &text[source_end..] {
+        rest if rest.starts_with(':') => (None, &rest[1..]),
+        rest if rest.starts_with('(') => (Some(')'), &rest[1..]),
+        _ => return None,
+    };
+
+    let line_end = rest.find(|c: char| !c.is_ascii_digit())?;
+    let line_number = rest[..line_end].parse().ok()?;
+    let rest = &rest[line_end..];
+
+    return match close {
+        Some(close) => Some((source, line_number, rest.strip_prefix(close)?)),
+        None => Some((source, line_number, rest)),
+    };
+}
+
+/// Modification time of the file on disk. Embedded files have none, so they never reload.
+fn modified_time(path: &str) -> Option<SystemTime> {
+    return resources::find(Path::new(path))
+        .ok()
+        .and_then(|file| fs::metadata(file).ok())
+        .and_then(|metadata| metadata.modified().ok());
+}
+
+fn to_cstring(code: String, path: &str) -> Result<CString> {
+    return CString::new(code).map_err(|e| Error::Decode {
+        path: path.to_string(),
+        message: e.to_string(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Log of the link error, which must be returned before any file is read
+    fn stage_error(builder: ShaderBuilder) -> String {
+        return match builder.build() {
+            Err(Error::Link { log, .. }) => log,
+            Err(e) => panic!("expected a link error, got {}", e),
+            Ok(_) => panic!("expected a link error"),
+        };
+    }
+
+    #[test]
+    fn invalid_stage_combinations_are_rejected() {
+        assert_eq!(stage_error(Shader::builder()), "program has no stages");
+        assert_eq!(
+            stage_error(
+                Shader::builder()
+                    .vertex("Missing.vert")
+                    .compute("Missing.comp")
+            ),
+            "compute stage cannot be linked with other stages"
+        );
+        assert_eq!(
+            stage_error(Shader::builder().fragment("Missing.frag")),
+            "program has no vertex stage"
+        );
+        assert_eq!(
+            stage_error(
+                Shader::builder()
+                    .vertex("Missing.vert")
+                    .stage(ShaderStage::TessControl, "Missing.tesc")
+                    .fragment("Missing.frag")
+            ),
+            "tessellation control stage needs a tessellation evaluation stage"
+        );
+    }
+
+    #[test]
+    fn samplers_and_images_are_set_with_ints() {
+        let handles = [
+            gl::SAMPLER_2D,
+            gl::SAMPLER_CUBE_MAP_ARRAY_SHADOW,
+            gl::INT_SAMPLER_CUBE,
+            gl::UNSIGNED_INT_SAMPLER_CUBE,
+            gl::INT_SAMPLER_2D_ARRAY,
+            gl::UNSIGNED_INT_SAMPLER_2D_ARRAY,
+            gl::IMAGE_2D,
+            gl::INT_IMAGE_3D,
+            gl::UNSIGNED_INT_IMAGE_BUFFER,
+        ];
+        for gl_type in handles {
+            assert!(type_matches(gl::INT, gl_type), "{:#x}", gl_type);
+            assert!(!type_matches(gl::FLOAT, gl_type), "{:#x}", gl_type);
+        }
+        assert!(!type_matches(gl::INT, gl::FLOAT_VEC2));
+    }
+
+    #[test]
+    fn driver_log_locations_are_mapped_to_files() {
+        let files = [String::from("Basic.frag"), String::from("Lighting.glsl")];
+
+        // NVIDIA
+        assert_eq!(
+            map_log_lines("0(12) : error C0000: syntax error\n", &files),
+            "Basic.frag:12 : error C0000: syntax error\n"
+        );
+        // Mesa
+        assert_eq!(
+            map_log_lines("1:7(3): error: `x' undeclared\n", &files),
+            "Lighting.glsl:7(3): error: `x' undeclared\n"
+        );
+        // AMD and Intel on Windows put the severity first
+        assert_eq!(
+            map_log_lines("ERROR: 1:7: 'x' : undeclared identifier\n", &files),
+            "ERROR: Lighting.glsl:7: 'x' : undeclared identifier\n"
+        );
+    }
+
+    #[test]
+    fn unmapped_log_lines_are_kept() {
+        let files = [String::from("Basic.frag")];
+        let log = "Fragment info\n-------------\n3(4) : error C0000: unknown source\n\
+                   ERROR: 1 compilation errors.  No code generated.\n";
+
+        assert_eq!(map_log_lines(log, &files), log);
+    }
+
+    #[test]
+    fn log_location_formats() {
+        assert_eq!(
+            parse_log_location("0(12) : error"),
+            Some((0, 12, " : error"))
+        );
+        assert_eq!(
+            parse_log_location("2:5(10): error"),
+            Some((2, 5, "(10): error"))
+        );
+        assert_eq!(parse_log_location("0(12 : error"), None);
+        assert_eq!(parse_log_location("error: 0:12"), None);
+        assert_eq!(parse_log_location("12"), None);
     }
 }
@@ -1,17 +1,7 @@
 // Request GLSL 4.0
 #version 400
 
-struct Material {
-    float shininess;
-    vec3 specular;
-};
-
-struct Light {
-    vec3 ambient;
-    vec3 diffuse;
-    vec3 direction;
-    vec3 specular;
-};
+#include "Lighting.glsl"
 
 in float frag_alpha;
 in vec3 frag_position;
@@ -20,26 +10,67 @@ in vec2 frag_tex_coord;
 
 out vec4 final_color;
 
-uniform Light uLight;
 uniform Material uMaterial;
+#ifdef DIFFUSE_TEXTURE
 uniform sampler2D uScreenTexture;
-uniform vec3 uViewPosition;
+#endif
+#ifdef NORMAL_MAP
+uniform sampler2D uNormalTexture;
+#endif
+#ifdef ENVIRONMENT_MAP
+uniform samplerCube uEnvironmentMap;
+#endif
+
+#include "Camera.glsl"
+
+#ifdef NORMAL_MAP
+// Tangent frame from screen-space derivatives, so meshes need no tangent attribute
+vec3 perturb_normal(vec3 normal, vec3 position, vec2 tex_coord)
+{
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(tex_coord);
+    vec2 duv2 = dFdy(tex_coord);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+    float scale = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    mat3 tbn = mat3(tangent * scale, bitangent * scale, normal);
+
+    vec3 tangent_normal = texture(uNormalTexture, tex_coord).xyz * 2.0 - 1.0;
+    return normalize(tbn * tangent_normal);
+}
+#endif
 
 void main()
 {
-    vec3 ambient = uLight.ambient * texture(uScreenTexture, frag_tex_coord).rgb;
+    vec3 base_color = vec3(1.0);
+#ifdef DIFFUSE_TEXTURE
+    base_color = texture(uScreenTexture, frag_tex_coord).rgb;
+#endif
+
+    vec3 ambient = uLight.ambient * uMaterial.ambient * base_color;
 
     vec3 normal = normalize(frag_normal);
+#ifdef NORMAL_MAP
+    normal = perturb_normal(normal, frag_position, frag_tex_coord);
+#endif
     vec3 light_direction = normalize(-uLight.direction);
     float diff = max(dot(normal, light_direction), 0.0);
-    vec3 diffuse = uLight.diffuse * diff * texture(uScreenTexture, frag_tex_coord).rgb;
+    vec3 diffuse = uLight.diffuse * diff * uMaterial.diffuse * base_color;
 
-    vec3 view_direction = normalize(uViewPosition - frag_position);
+    vec3 view_direction = normalize(uCamera.position - frag_position);
     vec3 reflect_direction = reflect(-light_direction, normal);
     float spec = pow(max(dot(view_direction, reflect_direction), 0.0), uMaterial.shininess);
     vec3 specular = uLight.specular * spec * uMaterial.specular;
 
     vec3 result = ambient + diffuse + specular;
+#ifdef ENVIRONMENT_MAP
+    vec3 reflection = texture(uEnvironmentMap, reflect(-view_direction, normal)).rgb;
+    result = mix(result, reflection, uMaterial.reflectivity);
+#endif
 
-    final_color = vec4(result, frag_alpha);
+    final_color = vec4(result, frag_alpha * uMaterial.alpha);
 }
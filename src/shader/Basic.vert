@@ -4,6 +4,10 @@
 layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec3 in_normal;
 layout(location = 2) in vec2 in_tex_coord;
+#ifdef INSTANCED
+// Per-instance model matrix (uses locations 3 to 6)
+layout(location = 3) in mat4 in_model;
+#endif
 
 out float frag_alpha;
 out vec3 frag_position;
@@ -11,15 +15,21 @@ out vec3 frag_normal;
 out vec2 frag_tex_coord;
 
 uniform mat4 uModel;
-uniform mat4 uView;
-uniform mat4 uProjection;
 uniform float uAlpha;
 
+#include "Camera.glsl"
+
 void main()
 {
+#ifdef INSTANCED
+    mat4 model = uModel * in_model;
+#else
+    mat4 model = uModel;
+#endif
+
     frag_alpha = uAlpha;
-    frag_position = vec3(uModel * vec4(in_position, 1.0));
-    frag_normal = mat3(transpose(inverse(uModel))) * in_normal;
+    frag_position = vec3(model * vec4(in_position, 1.0));
+    frag_normal = mat3(transpose(inverse(model))) * in_normal;
     frag_tex_coord = in_tex_coord;
-    gl_Position = uProjection * uView * vec4(frag_position, 1.0);
+    gl_Position = uCamera.projection * uCamera.view * vec4(frag_position, 1.0);
 }
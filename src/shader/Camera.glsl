@@ -0,0 +1,6 @@
+// Shared by all programs, bound by `Shader::set_uniform_block`
+layout(std140) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    vec3 position;
+} uCamera;
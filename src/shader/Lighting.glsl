@@ -0,0 +1,15 @@
+struct Material {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+    float shininess;
+    float alpha;
+    float reflectivity;
+};
+
+layout(std140) uniform Light {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 direction;
+    vec3 specular;
+} uLight;
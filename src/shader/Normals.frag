@@ -0,0 +1,9 @@
+// Request GLSL 4.0
+#version 400
+
+out vec4 final_color;
+
+void main()
+{
+    final_color = vec4(1.0, 1.0, 0.0, 1.0);
+}
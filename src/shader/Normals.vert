@@ -0,0 +1,16 @@
+// Request GLSL 4.0
+#version 400
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+
+out vec3 geom_normal;
+
+uniform mat4 uModel;
+
+void main()
+{
+    // Projection is done in the geometry shader
+    geom_normal = normalize(mat3(transpose(inverse(uModel))) * in_normal);
+    gl_Position = uModel * vec4(in_position, 1.0);
+}
@@ -0,0 +1,13 @@
+// Request GLSL 4.0
+#version 400
+
+// Age as a fraction of the lifetime
+in float frag_age;
+
+out vec4 final_color;
+
+void main()
+{
+    // Yellow at launch, red at the end of the lifetime
+    final_color = vec4(mix(vec3(1.0, 0.9, 0.2), vec3(0.9, 0.1, 0.0), frag_age), 1.0);
+}
@@ -0,0 +1,19 @@
+// Request GLSL 4.0
+#version 400
+
+// xyz is the position, w the age in seconds
+layout(location = 0) in vec4 in_position;
+
+out float frag_age;
+
+#include "Camera.glsl"
+
+uniform float uLifetime;
+
+void main()
+{
+    frag_age = in_position.w / uLifetime;
+    gl_Position = uCamera.projection * uCamera.view * vec4(in_position.xyz, 1.0);
+    // Shrink towards the end of the lifetime
+    gl_PointSize = mix(6.0, 2.0, frag_age);
+}
@@ -0,0 +1,13 @@
+// Request GLSL 4.0
+#version 400
+
+in vec3 frag_direction;
+
+out vec4 final_color;
+
+uniform samplerCube uSkybox;
+
+void main()
+{
+    final_color = vec4(texture(uSkybox, frag_direction).rgb, 1.0);
+}
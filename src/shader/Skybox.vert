@@ -0,0 +1,17 @@
+// Request GLSL 4.0
+#version 400
+
+layout(location = 0) in vec3 in_position;
+
+out vec3 frag_direction;
+
+#include "Camera.glsl"
+
+void main()
+{
+    frag_direction = in_position;
+    // Only the rotation of the view, so the sky stays around the camera
+    vec4 position = uCamera.projection * mat4(mat3(uCamera.view)) * vec4(in_position, 1.0);
+    // Depth of the far plane
+    gl_Position = position.xyww;
+}
@@ -0,0 +1,664 @@
+use naga::front::glsl::{Frontend, Options, ParseErrors};
+use naga::{AddressSpace, ArraySize, Binding, Handle, Module, ScalarKind, Type, TypeInner};
+
+use crate::error::{Error, Result};
+use crate::preprocessor::{preprocess, PreprocessedSource};
+use crate::shader::ShaderStage;
+
+/// Name of the block wrapping a non-block uniform, which naga cannot parse on its own
+const LOOSE_UNIFORM_BLOCK: &str = "LooseUniform_";
+
+/// Variable passed from one stage to the next
+pub struct Varying {
+    pub name: String,
+    /// GLSL type name, e.g. `vec3`
+    pub type_name: String,
+}
+
+/// Declarations of one stage, read without a GL context
+pub struct StageInterface {
+    pub path: String,
+    /// `None` for stages naga has no frontend for (tessellation and geometry)
+    pub inputs: Option<Vec<Varying>>,
+    pub outputs: Option<Vec<Varying>>,
+    /// Uniform names as OpenGL reports them, with struct fields as `name.field`.
+    /// Fields are not listed for stages naga cannot parse.
+    pub uniforms: Vec<String>,
+    pub uniform_blocks: Vec<String>,
+}
+
+impl StageInterface {
+    pub fn has_uniform(&self, name: &str) -> bool {
+        return self.uniforms.iter().any(|uniform| uniform == name);
+    }
+
+    pub fn has_uniform_block(&self, name: &str) -> bool {
+        return self.uniform_blocks.iter().any(|block| block == name);
+    }
+}
+
+/// Preprocess and parse a stage with naga's GLSL frontend. Parse errors are
+/// returned as `Error::Compile` with the original file and line of each error.
+pub fn validate_stage(
+    stage: ShaderStage,
+    path: &str,
+    defines: &[(&str, &str)],
+) -> Result<StageInterface> {
+    let defines: Vec<(String, String)> = defines
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let source = preprocess(path, &defines)?;
+    let (code, declarations) = to_naga_glsl(&source.code);
+
+    let mut interface = StageInterface {
+        path: path.to_string(),
+        inputs: None,
+        outputs: None,
+        uniforms: [declarations.uniforms, declarations.opaque_uniforms.clone()].concat(),
+        uniform_blocks: declarations.uniform_blocks,
+    };
+
+    let naga_stage = match stage {
+        ShaderStage::Vertex => naga::ShaderStage::Vertex,
+        ShaderStage::Fragment => naga::ShaderStage::Fragment,
+        ShaderStage::Compute => naga::ShaderStage::Compute,
+        // Only the declarations found while rewriting the code are known
+        _ => return Ok(interface),
+    };
+
+    let module = Frontend::default()
+        .parse(&Options::from(naga_stage), &code)
+        .map_err(|errors| Error::Compile {
+            stage: stage.name(),
+            path: path.to_string(),
+            log: parse_log(&errors, &code, &source),
+        })?;
+
+    let entry_point = match module.entry_points.first() {
+        Some(entry_point) => entry_point,
+        None => {
+            return Err(Error::Compile {
+                stage: stage.name(),
+                path: path.to_string(),
+                log: String::from("no main function\n"),
+            })
+        }
+    };
+
+    let inputs = entry_point
+        .function
+        .arguments
+        .iter()
+        .filter(|argument| matches!(argument.binding, Some(Binding::Location { .. })))
+        .map(|argument| Varying {
+            name: argument.name.clone().unwrap_or_default(),
+            type_name: type_name(&module, argument.ty),
+        })
+        .collect();
+
+    let mut outputs = Vec::new();
+    if let Some(result) = &entry_point.function.result {
+        if let TypeInner::Struct { members, .. } = &module.types[result.ty].inner {
+            for member in members {
+                if let Some(Binding::Location { .. }) = member.binding {
+                    outputs.push(Varying {
+                        name: member.name.clone().unwrap_or_default(),
+                        type_name: type_name(&module, member.ty),
+                    });
+                }
+            }
+        }
+    }
+
+    interface.inputs = Some(inputs);
+    interface.outputs = Some(outputs);
+    interface.uniforms = [uniform_names(&module), declarations.opaque_uniforms].concat();
+
+    return Ok(interface);
+}
+
+/// Validate every stage, then check that each input of a stage is written with
+/// the same type by the previous one. Stage pairs involving a stage naga cannot
+/// parse are not checked. Interface mismatches are returned as `Error::Link`.
+pub fn validate_program(
+    stages: &[(ShaderStage, &str)],
+    defines: &[(&str, &str)],
+) -> Result<Vec<StageInterface>> {
+    let mut interfaces = Vec::with_capacity(stages.len());
+    for (stage, path) in stages {
+        interfaces.push(validate_stage(*stage, path, defines)?);
+    }
+
+    let mut log = String::new();
+    for pair in interfaces.windows(2) {
+        let (outputs, inputs) = match (&pair[0].outputs, &pair[1].inputs) {
+            (Some(outputs), Some(inputs)) => (outputs, inputs),
+            _ => continue,
+        };
+
+        for input in inputs {
+            match outputs.iter().find(|output| output.name == input.name) {
+                Some(output) if output.type_name != input.type_name => {
+                    log.push_str(&format!(
+                        "{} is {} in {} but {} in {}\n",
+                        input.name, output.type_name, pair[0].path, input.type_name, pair[1].path
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    log.push_str(&format!(
+                        "{} is read by {} but not written by {}\n",
+                        input.name, pair[1].path, pair[0].path
+                    ));
+                }
+            }
+        }
+    }
+
+    if !log.is_empty() {
+        let paths: Vec<&str> = stages.iter().map(|(_, path)| *path).collect();
+        return Err(Error::Link {
+            program: paths.join(" + "),
+            log,
+        });
+    }
+
+    return Ok(interfaces);
+}
+
+/// Uniforms and blocks declared at the start of a line
+struct Declarations {
+    uniforms: Vec<String>,
+    /// Opaque uniforms (samplers and images), which are not put in blocks
+    opaque_uniforms: Vec<String>,
+    uniform_blocks: Vec<String>,
+}
+
+/// Rewrite the code for naga, which follows Vulkan GLSL: `#version 450`,
+/// explicit bindings on blocks and opaque uniforms, other uniforms wrapped in
+/// blocks and samplers split into a texture and a sampler. `#line` directives
+/// are added where lines are inserted, so `source_location` still finds the
+/// original line.
+fn to_naga_glsl(code: &str) -> (String, Declarations) {
+    let mut rewritten = String::with_capacity(code.len());
+    let mut declarations = Declarations {
+        uniforms: Vec::new(),
+        opaque_uniforms: Vec::new(),
+        uniform_blocks: Vec::new(),
+    };
+    let mut binding = 0;
+    // Location of the current line, as in `source_location`
+    let mut file = 0;
+    let mut line_number = 1;
+
+    for line in code.lines() {
+        let trimmed = line.trim_start();
+        let (file_here, line_here) = (file, line_number);
+        match parse_line_directive(line) {
+            Some((next_line, next_file)) => {
+                line_number = next_line;
+                file = next_file.unwrap_or(file);
+            }
+            None => line_number += 1,
+        }
+
+        if trimmed.starts_with("#version") {
+            rewritten.push_str("#version 450\n");
+            continue;
+        }
+
+        // `layout(...) uniform ...` or `uniform ...`
+        let (layout, rest) = match trimmed.strip_prefix("layout") {
+            Some(layout) => match layout.find(')') {
+                Some(close) => (Some(&layout[..close]), layout[close + 1..].trim_start()),
+                None => (None, trimmed),
+            },
+            None => (None, trimmed),
+        };
+        let declaration = match rest.strip_prefix("uniform ") {
+            Some(declaration) => declaration.trim_start(),
+            None => {
+                rewritten.push_str(line);
+                rewritten.push('\n');
+                continue;
+            }
+        };
+
+        let layout = match layout {
+            Some(layout) if layout.contains("binding") => format!("layout{})", layout),
+            Some(layout) => format!("layout{}, binding = {})", layout, binding),
+            None => format!("layout(binding = {})", binding),
+        };
+        binding += 1;
+
+        let mut words = declaration.split_whitespace();
+        let ty = words.next().unwrap_or("");
+        let is_block = declaration.contains('{') || !declaration.contains(';');
+
+        if is_block {
+            let name = ty.trim_end_matches('{');
+            declarations.uniform_blocks.push(name.to_string());
+            rewritten.push_str(&format!("{} uniform {}\n", layout, declaration));
+            continue;
+        }
+
+        let name = words
+            .next()
+            .unwrap_or("")
+            .split(['[', ';', '='])
+            .next()
+            .unwrap_or("");
+
+        if ty.contains("sampler") {
+            // `sampler2D name` becomes `texture2D name_texture` and `sampler name_sampler`,
+            // and `name` is replaced by `sampler2D(name_texture, name_sampler)`
+            let texture_ty = ty.replace("sampler", "texture").replace("Shadow", "");
+            let sampler_ty = if ty.ends_with("Shadow") {
+                "samplerShadow"
+            } else {
+                "sampler"
+            };
+            rewritten.push_str(&format!(
+                "{} uniform {} {}_texture; layout(binding = {}) uniform {} {}_sampler;\n",
+                layout, texture_ty, name, binding, sampler_ty, name
+            ));
+            rewritten.push_str(&format!(
+                "#define {} {}({}_texture, {}_sampler)\n",
+                name, ty, name, name
+            ));
+            rewritten.push_str(&format!("#line {} {}\n", line_here + 1, file_here));
+            binding += 1;
+            declarations.opaque_uniforms.push(name.to_string());
+        } else if ty.contains("image") {
+            rewritten.push_str(&format!("{} uniform {}\n", layout, declaration));
+            declarations.opaque_uniforms.push(name.to_string());
+        } else {
+            rewritten.push_str(&format!(
+                "{} uniform {}{} {{ {} }};\n",
+                layout, LOOSE_UNIFORM_BLOCK, name, declaration
+            ));
+            declarations.uniforms.push(name.to_string());
+        }
+    }
+
+    return (rewritten, declarations);
+}
+
+/// Names of uniforms in the blocks added by `to_naga_glsl`, with struct fields expanded
+fn uniform_names(module: &Module) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for (_, variable) in module.global_variables.iter() {
+        if variable.space != AddressSpace::Uniform {
+            continue;
+        }
+
+        let block = &module.types[variable.ty];
+        let is_loose = block
+            .name
+            .as_deref()
+            .is_some_and(|name| name.starts_with(LOOSE_UNIFORM_BLOCK));
+        if let (true, TypeInner::Struct { members, .. }) = (is_loose, &block.inner) {
+            for member in members {
+                let name = member.name.clone().unwrap_or_default();
+                push_uniform_names(module, &name, member.ty, &mut names);
+            }
+        }
+    }
+
+    return names;
+}
+
+/// Add `name`, or the name of each field if `ty` is a struct or an array of structs
+fn push_uniform_names(module: &Module, name: &str, ty: Handle<Type>, names: &mut Vec<String>) {
+    match &module.types[ty].inner {
+        TypeInner::Struct { members, .. } => {
+            for member in members {
+                let field = format!("{}.{}", name, member.name.as_deref().unwrap_or(""));
+                push_uniform_names(module, &field, member.ty, names);
+            }
+        }
+        TypeInner::Array {
+            base,
+            size: ArraySize::Constant(size),
+            ..
+        } if matches!(module.types[*base].inner, TypeInner::Struct { .. }) => {
+            for index in 0..size.get() {
+                push_uniform_names(module, &format!("{}[{}]", name, index), *base, names);
+            }
+        }
+        _ => names.push(name.to_string()),
+    }
+}
+
+/// GLSL name of a scalar, vector or matrix type
+fn type_name(module: &Module, ty: Handle<Type>) -> String {
+    let prefix = |kind| match kind {
+        ScalarKind::Sint => "i",
+        ScalarKind::Uint => "u",
+        ScalarKind::Bool => "b",
+        _ => "",
+    };
+
+    return match &module.types[ty].inner {
+        TypeInner::Scalar(scalar) => match scalar.kind {
+            ScalarKind::Sint => String::from("int"),
+            ScalarKind::Uint => String::from("uint"),
+            ScalarKind::Bool => String::from("bool"),
+            _ => String::from("float"),
+        },
+        TypeInner::Vector { size, scalar } => {
+            format!("{}vec{}", prefix(scalar.kind), *size as u32)
+        }
+        TypeInner::Matrix { columns, rows, .. } if columns == rows => {
+            format!("mat{}", *columns as u32)
+        }
+        TypeInner::Matrix { columns, rows, .. } => {
+            format!("mat{}x{}", *columns as u32, *rows as u32)
+        }
+        inner => format!("{:?}", inner),
+    };
+}
+
+/// Log with one `file:line: message` line per error
+fn parse_log(errors: &ParseErrors, code: &str, source: &PreprocessedSource) -> String {
+    let mut log = String::new();
+
+    for error in &errors.errors {
+        match error.meta.to_range() {
+            Some(range) => {
+                let (file, line) = source_location(code, &source.files, range.start);
+                log.push_str(&format!("{}:{}: {}\n", file, line, error.kind));
+            }
+            None => log.push_str(&format!("{}\n", error.kind)),
+        }
+    }
+
+    return log;
+}
+
+/// File and line of a byte offset in preprocessed code, following its `#line` directives
+fn source_location(code: &str, files: &[String], offset: usize) -> (String, usize) {
+    let mut file = 0;
+    let mut line = 1;
+    let mut start = 0;
+
+    for text in code.split_inclusive('\n') {
+        if offset < start + text.len() {
+            break;
+        }
+        start += text.len();
+
+        match parse_line_directive(text) {
+            Some((next_line, next_file)) => {
+                line = next_line;
+                file = next_file.unwrap_or(file);
+            }
+            None => line += 1,
+        }
+    }
+
+    let file = files.get(file).cloned().unwrap_or_default();
+
+    return (file, line);
+}
+
+/// Line and source string number of `#line line [file]`
+fn parse_line_directive(text: &str) -> Option<(usize, Option<usize>)> {
+    let mut arguments = text.trim().strip_prefix("#line")?.split_whitespace();
+    let line = arguments.next()?.parse().ok()?;
+
+    return Some((line, arguments.next().and_then(|file| file.parse().ok())));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::material::MaterialUniform;
+    use crate::shader::UniformStruct;
+    use crate::shader_variants::ShaderFeatures;
+    use crate::uniform_names::*;
+
+    const SHADER_DIR: &str = "src/shader";
+
+    /// Every combination of the features `ShaderVariants` compiles
+    fn feature_sets() -> Vec<ShaderFeatures> {
+        let features = [
+            ShaderFeatures::DIFFUSE_TEXTURE,
+            ShaderFeatures::NORMAL_MAP,
+            ShaderFeatures::INSTANCED,
+            ShaderFeatures::ENVIRONMENT_MAP,
+        ];
+
+        return (0..1 << features.len())
+            .map(|mask: u32| {
+                features
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| mask & (1 << index) != 0)
+                    .fold(ShaderFeatures::NONE, |set, (_, feature)| set | *feature)
+            })
+            .collect();
+    }
+
+    fn basic_program(features: ShaderFeatures) -> Vec<StageInterface> {
+        let stages = [
+            (ShaderStage::Vertex, "src/shader/Basic.vert"),
+            (ShaderStage::Fragment, "src/shader/Basic.frag"),
+        ];
+
+        return validate_program(&stages, &features.defines())
+            .unwrap_or_else(|e| panic!("{} with {}", e, features));
+    }
+
+    fn normals_program() -> Vec<StageInterface> {
+        let stages = [
+            (ShaderStage::Vertex, "src/shader/Normals.vert"),
+            (ShaderStage::Geometry, "src/shader/Normals.geom"),
+            (ShaderStage::Fragment, "src/shader/Normals.frag"),
+        ];
+
+        return validate_program(&stages, &[]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn skybox_program() -> Vec<StageInterface> {
+        let stages = [
+            (ShaderStage::Vertex, "src/shader/Skybox.vert"),
+            (ShaderStage::Fragment, "src/shader/Skybox.frag"),
+        ];
+
+        return validate_program(&stages, &[]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn particles_program() -> Vec<StageInterface> {
+        let stages = [
+            (ShaderStage::Vertex, "src/shader/Particles.vert"),
+            (ShaderStage::Fragment, "src/shader/Particles.frag"),
+        ];
+
+        return validate_program(&stages, &[]).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn assert_uniforms(program: &[StageInterface], uniforms: &[&CStr], blocks: &[&CStr]) {
+        let names = |names: &[&CStr]| -> Vec<String> {
+            return names
+                .iter()
+                .map(|name| name.to_str().unwrap().to_string())
+                .collect();
+        };
+        assert_uniform_names(program, &names(uniforms), &names(blocks));
+    }
+
+    fn assert_uniform_names(program: &[StageInterface], uniforms: &[String], blocks: &[String]) {
+        for name in uniforms {
+            assert!(
+                program.iter().any(|stage| stage.has_uniform(name)),
+                "uniform {} is not declared by {}",
+                name,
+                program[0].path
+            );
+        }
+        for name in blocks {
+            assert!(
+                program.iter().any(|stage| stage.has_uniform_block(name)),
+                "uniform block {} is not declared by {}",
+                name,
+                program[0].path
+            );
+        }
+    }
+
+    /// Directory with the given files, removed by the caller
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust3d_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, code) in files {
+            fs::write(dir.join(file), code).unwrap();
+        }
+
+        return dir;
+    }
+
+    #[test]
+    fn shipped_shaders_parse() {
+        for entry in fs::read_dir(SHADER_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            let stage = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("vert") => ShaderStage::Vertex,
+                Some("geom") => ShaderStage::Geometry,
+                Some("frag") => ShaderStage::Fragment,
+                Some("comp") => ShaderStage::Compute,
+                _ => continue,
+            };
+
+            for features in feature_sets() {
+                if let Err(e) = validate_stage(stage, path.to_str().unwrap(), &features.defines()) {
+                    panic!("{} with {}", e, features);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vertex_outputs_match_fragment_inputs() {
+        for features in feature_sets() {
+            basic_program(features);
+        }
+        normals_program();
+        skybox_program();
+        particles_program();
+    }
+
+    #[test]
+    fn uniforms_set_by_main_exist() {
+        // `Mesh::draw`, `Material::apply` and the `uAlpha` callback in main.rs
+        let members: Vec<String> = MaterialUniform::MEMBERS
+            .iter()
+            .map(|member| format!("{}.{}", MATERIAL.to_str().unwrap(), member))
+            .collect();
+        for features in feature_sets() {
+            let program = basic_program(features);
+            assert_uniforms(&program, &[MODEL, ALPHA], &[CAMERA_BLOCK, LIGHT_BLOCK]);
+            assert_uniform_names(&program, &members, &[]);
+        }
+        assert_uniforms(
+            &basic_program(ShaderFeatures::DIFFUSE_TEXTURE),
+            &[DIFFUSE_TEXTURE],
+            &[],
+        );
+        assert_uniforms(
+            &basic_program(ShaderFeatures::NORMAL_MAP),
+            &[NORMAL_TEXTURE],
+            &[],
+        );
+        assert_uniforms(
+            &basic_program(ShaderFeatures::ENVIRONMENT_MAP),
+            &[ENVIRONMENT_MAP],
+            &[],
+        );
+
+        assert_uniforms(&normals_program(), &[MODEL, NORMAL_LENGTH], &[CAMERA_BLOCK]);
+        assert_uniforms(&skybox_program(), &[SKYBOX], &[CAMERA_BLOCK]);
+        assert_uniforms(&particles_program(), &[PARTICLE_LIFETIME], &[CAMERA_BLOCK]);
+
+        // `Particles::update` when compute shaders are supported
+        let simulation = validate_stage(ShaderStage::Compute, "src/shader/Particles.comp", &[])
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_uniforms(
+            &[simulation],
+            &[
+                PARTICLE_DELTA,
+                PARTICLE_LIFETIME,
+                PARTICLE_LAUNCH_SPEED,
+                PARTICLE_SPREAD,
+                PARTICLE_GRAVITY,
+            ],
+            &[],
+        );
+    }
+
+    #[test]
+    fn parse_errors_refer_to_source_line() {
+        let dir = write_files(
+            "parse_error",
+            &[
+                (
+                    "Main.frag",
+                    "#version 400\n#include \"Color.glsl\"\nout vec4 color;\nvoid main()\n{\n    color = vec4(tint, 1.0);\n}\n",
+                ),
+                ("Color.glsl", "// Tint\nvec3 tint = vec3(1.0) +;\n"),
+            ],
+        );
+        let path = dir.join("Main.frag");
+        let result = validate_stage(ShaderStage::Fragment, path.to_str().unwrap(), &[("A", "1")]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Err(Error::Compile { log, .. }) => {
+                let location = format!("{}:2: ", dir.join("Color.glsl").display());
+                assert!(log.starts_with(&location), "{}", log);
+            }
+            _ => panic!("expected a compile error"),
+        }
+    }
+
+    #[test]
+    fn mismatched_varyings_fail() {
+        let dir = write_files(
+            "varyings",
+            &[
+                (
+                    "Main.vert",
+                    "#version 400\nout vec3 color;\nvoid main()\n{\n    color = vec3(1.0);\n    gl_Position = vec4(0.0);\n}\n",
+                ),
+                (
+                    "Main.frag",
+                    "#version 400\nin vec2 color;\nin float depth;\nout vec4 final_color;\nvoid main()\n{\n    final_color = vec4(color, depth, 1.0);\n}\n",
+                ),
+            ],
+        );
+        let vertex = dir.join("Main.vert");
+        let fragment = dir.join("Main.frag");
+        let result = validate_program(
+            &[
+                (ShaderStage::Vertex, vertex.to_str().unwrap()),
+                (ShaderStage::Fragment, fragment.to_str().unwrap()),
+            ],
+            &[],
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Err(Error::Link { log, .. }) => {
+                assert!(log.contains("color is vec3 in"), "{}", log);
+                assert!(log.contains("depth is read by"), "{}", log);
+            }
+            _ => panic!("expected a link error"),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::error::Result;
+use crate::shader::{Shader, ShaderBuilder};
+use crate::uniform_buffer::{Std140, UniformBuffer};
+
+/// Set of optional shader features. Each feature is compiled in with `#define NAME 1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures(u32);
+
+impl ShaderFeatures {
+    pub const NONE: ShaderFeatures = ShaderFeatures(0);
+    /// Base color is read from `uScreenTexture`
+    pub const DIFFUSE_TEXTURE: ShaderFeatures = ShaderFeatures(1 << 0);
+    /// Normals are perturbed by `uNormalTexture`
+    pub const NORMAL_MAP: ShaderFeatures = ShaderFeatures(1 << 1);
+    /// Model matrix is also read per instance
+    pub const INSTANCED: ShaderFeatures = ShaderFeatures(1 << 2);
+    /// Surfaces reflect the cubemap `uEnvironmentMap` by `uMaterial.reflectivity`
+    pub const ENVIRONMENT_MAP: ShaderFeatures = ShaderFeatures(1 << 3);
+
+    const DEFINES: [(ShaderFeatures, &'static str); 4] = [
+        (ShaderFeatures::DIFFUSE_TEXTURE, "DIFFUSE_TEXTURE"),
+        (ShaderFeatures::NORMAL_MAP, "NORMAL_MAP"),
+        (ShaderFeatures::INSTANCED, "INSTANCED"),
+        (ShaderFeatures::ENVIRONMENT_MAP, "ENVIRONMENT_MAP"),
+    ];
+
+    pub fn contains(self, features: ShaderFeatures) -> bool {
+        return self.0 & features.0 == features.0;
+    }
+
+    /// `#define`s enabling the features
+    pub fn defines(self) -> Vec<(&'static str, &'static str)> {
+        return ShaderFeatures::DEFINES
+            .iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|(_, name)| (*name, "1"))
+            .collect();
+    }
+}
+
+impl BitOr for ShaderFeatures {
+    type Output = ShaderFeatures;
+
+    fn bitor(self, other: ShaderFeatures) -> ShaderFeatures {
+        return ShaderFeatures(self.0 | other.0);
+    }
+}
+
+impl BitOrAssign for ShaderFeatures {
+    fn bitor_assign(&mut self, other: ShaderFeatures) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Display for ShaderFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = ShaderFeatures::DEFINES
+            .iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|(_, name)| *name)
+            .collect();
+
+        if names.is_empty() {
+            return write!(f, "NONE");
+        }
+
+        return write!(f, "{}", names.join(" | "));
+    }
+}
+
+/// Programs built from the same sources with different features,
+/// compiled the first time each combination is requested
+pub struct ShaderVariants {
+    /// Stages and options shared by every variant
+    builder: ShaderBuilder,
+    variants: HashMap<ShaderFeatures, Shader>,
+    /// Variants that failed to build. They are retried after the sources change.
+    errors: HashMap<ShaderFeatures, String>,
+    /// Uniform block bindings applied to every variant
+    block_bindings: Vec<(String, u32)>,
+}
+
+impl ShaderVariants {
+    /// Variants of the program `builder` describes, with the feature defines added
+    pub fn from_builder(builder: ShaderBuilder) -> ShaderVariants {
+        return ShaderVariants {
+            builder,
+            variants: HashMap::new(),
+            errors: HashMap::new(),
+            block_bindings: Vec::new(),
+        };
+    }
+
+    /// Variant with the features, building it if needed. Returns `None` if it fails to build.
+    pub fn get(&mut self, features: ShaderFeatures) -> Option<&Shader> {
+        if !self.variants.contains_key(&features) && !self.errors.contains_key(&features) {
+            match self.builder.clone().defines(&features.defines()).build() {
+                Ok(shader) => {
+                    for (name, binding) in &self.block_bindings {
+                        unsafe {
+                            shader.bind_uniform_block(name, *binding);
+                        }
+                    }
+                    self.variants.insert(features, shader);
+                }
+                Err(e) => {
+                    self.errors.insert(features, e.to_string());
+                }
+            }
+        }
+
+        return self.variants.get(&features);
+    }
+
+    /// Variants built so far
+    pub fn iter(&self) -> impl Iterator<Item = (&ShaderFeatures, &Shader)> {
+        return self.variants.iter();
+    }
+
+    /// Build errors of variants, for display
+    pub fn errors(&self) -> impl Iterator<Item = (&ShaderFeatures, &String)> {
+        return self.errors.iter();
+    }
+
+    /// Bind the uniform block of every variant, including ones built later, to `buffer`
+    pub unsafe fn set_uniform_block<T: Std140>(&mut self, name: &CStr, buffer: &UniformBuffer<T>) {
+        for shader in self.variants.values() {
+            shader.set_uniform_block(name, buffer);
+        }
+
+        self.block_bindings
+            .push((name.to_string_lossy().into_owned(), buffer.binding()));
+    }
+
+    /// Reload variants whose sources changed and retry failed ones.
+    /// Returns whether any variant was replaced, or the first reload error.
+    pub fn reload_if_modified(&mut self) -> Result<bool> {
+        let mut reloaded = false;
+        let mut result = Ok(());
+        for shader in self.variants.values_mut() {
+            match shader.reload_if_modified() {
+                Ok(true) => reloaded = true,
+                Ok(false) => {}
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        // Failed variants share the sources, so retry them once the others notice a change
+        if reloaded || result.is_err() {
+            self.errors.clear();
+        }
+
+        result?;
+
+        return Ok(reloaded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_feature_has_its_define() {
+        let features = [
+            (ShaderFeatures::DIFFUSE_TEXTURE, "DIFFUSE_TEXTURE"),
+            (ShaderFeatures::NORMAL_MAP, "NORMAL_MAP"),
+            (ShaderFeatures::INSTANCED, "INSTANCED"),
+            (ShaderFeatures::ENVIRONMENT_MAP, "ENVIRONMENT_MAP"),
+        ];
+        for (feature, name) in features {
+            assert_eq!(feature.defines(), [(name, "1")]);
+        }
+    }
+
+    #[test]
+    fn no_features_have_no_defines() {
+        assert!(ShaderFeatures::NONE.defines().is_empty());
+        assert!(ShaderFeatures::default().defines().is_empty());
+    }
+
+    #[test]
+    fn combined_features_are_displayed_in_define_order() {
+        let features = ShaderFeatures::ENVIRONMENT_MAP | ShaderFeatures::DIFFUSE_TEXTURE;
+
+        assert_eq!(
+            features.defines(),
+            [("DIFFUSE_TEXTURE", "1"), ("ENVIRONMENT_MAP", "1")]
+        );
+        assert_eq!(features.to_string(), "DIFFUSE_TEXTURE | ENVIRONMENT_MAP");
+        assert_eq!(ShaderFeatures::NORMAL_MAP.to_string(), "NORMAL_MAP");
+        assert_eq!(ShaderFeatures::NONE.to_string(), "NONE");
+    }
+}
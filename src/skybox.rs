@@ -0,0 +1,78 @@
+use crate::error::Result;
+use crate::shader::Shader;
+use crate::uniform_names;
+use crate::vertex::{SkyboxVertex, Vertex};
+
+/// Cubemap drawn around the camera, behind everything else
+pub struct Skybox {
+    vertex: Vertex,
+    shader: Shader,
+}
+
+impl Skybox {
+    /// `shader` is built from Skybox.vert and Skybox.frag, and needs the
+    /// Camera block bound with `shader().set_uniform_block`
+    pub fn new(shader: Shader) -> Skybox {
+        let corners = [
+            [-1.0, -1.0, -1.0],
+            [1.0, -1.0, -1.0],
+            [1.0, 1.0, -1.0],
+            [-1.0, 1.0, -1.0],
+            [-1.0, -1.0, 1.0],
+            [1.0, -1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [-1.0, 1.0, 1.0],
+        ];
+        // Two triangles per face, facing inwards
+        let indices: [usize; 36] = [
+            0, 1, 2, 0, 2, 3, // -Z
+            4, 6, 5, 4, 7, 6, // +Z
+            0, 7, 4, 0, 3, 7, // -X
+            1, 6, 2, 1, 5, 6, // +X
+            0, 5, 1, 0, 4, 5, // -Y
+            3, 6, 7, 3, 2, 6, // +Y
+        ];
+        let vertices: Vec<SkyboxVertex> = indices
+            .iter()
+            .map(|index| SkyboxVertex {
+                position: corners[*index],
+            })
+            .collect();
+
+        return Skybox {
+            vertex: Vertex::new(&vertices, gl::STATIC_DRAW),
+            shader,
+        };
+    }
+
+    pub fn shader(&self) -> &Shader {
+        return &self.shader;
+    }
+
+    pub fn reload_if_modified(&mut self) -> Result<bool> {
+        return self.shader.reload_if_modified();
+    }
+
+    /// Draw the cubemap texture `cubemap` with the rotation of the current view.
+    /// Draw it before the scene: it lies on the far plane and does not write
+    /// depth, so everything drawn afterwards covers it.
+    pub unsafe fn draw(&self, cubemap: u32) {
+        let mut depth_mask = gl::TRUE;
+        gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+        let mut depth_func = gl::LESS as i32;
+        gl::GetIntegerv(gl::DEPTH_FUNC, &mut depth_func);
+
+        gl::DepthMask(gl::FALSE);
+        gl::DepthFunc(gl::LEQUAL);
+
+        self.shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+        self.shader.set_sampler(uniform_names::SKYBOX, 0);
+        self.vertex.draw();
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+
+        gl::DepthFunc(depth_func as u32);
+        gl::DepthMask(depth_mask);
+    }
+}
@@ -1,11 +1,99 @@
 use std::collections::HashMap;
+use std::f32::consts::PI;
 use std::os::raw::c_void;
 use std::path::Path;
+use std::ptr;
 
-use image::DynamicImage;
+use gl::types::*;
+use image::{DynamicImage, ImageError, ImageFormat};
+
+use crate::common::print_warning_log;
+use crate::compressed_texture::{is_compressed_file, CompressedImage};
+use crate::error::{Error, Result};
+use crate::gl_object::Texture;
+use crate::resources;
+use crate::sampler::SamplerDesc;
+
+/// How an image becomes a texture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureDesc {
+    pub sampler: SamplerDesc,
+    /// Store 8-bit color images as sRGB so that sampling returns linear values.
+    /// For color textures only, not for data such as normal maps.
+    pub srgb: bool,
+    /// Generate mipmaps. Without them, mipmap filtering is turned off.
+    pub mipmaps: bool,
+    /// Flip the image upside down, since its first row is the top but texture
+    /// coordinate 0 is the bottom in OpenGL
+    pub flip_vertically: bool,
+}
+
+impl Default for TextureDesc {
+    fn default() -> TextureDesc {
+        return TextureDesc {
+            sampler: SamplerDesc::default(),
+            srgb: false,
+            mipmaps: true,
+            flip_vertically: true,
+        };
+    }
+}
+
+/// Layout of the voxels of a raw volume, stored x first, then y, then z
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VolumeFormat {
+    R8,
+    /// Little-endian 16-bit values
+    R16,
+    R32F,
+    Rgba8,
+}
+
+impl VolumeFormat {
+    /// Format written in lowercase, as on the command line (`r8`, `r16`, `r32f`, `rgba8`)
+    pub fn from_name(name: &str) -> Option<VolumeFormat> {
+        return match name {
+            "r8" => Some(VolumeFormat::R8),
+            "r16" => Some(VolumeFormat::R16),
+            "r32f" => Some(VolumeFormat::R32F),
+            "rgba8" => Some(VolumeFormat::Rgba8),
+            _ => None,
+        };
+    }
+
+    fn bytes_per_voxel(self) -> usize {
+        return match self {
+            VolumeFormat::R8 => 1,
+            VolumeFormat::R16 => 2,
+            VolumeFormat::R32F | VolumeFormat::Rgba8 => 4,
+        };
+    }
+
+    fn pixel_format(self) -> PixelFormat {
+        let (internal_format, format, data_type) = match self {
+            VolumeFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            VolumeFormat::R16 => (gl::R16, gl::RED, gl::UNSIGNED_SHORT),
+            VolumeFormat::R32F => (gl::R32F, gl::RED, gl::FLOAT),
+            VolumeFormat::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+        };
+
+        return PixelFormat {
+            internal_format,
+            format,
+            data_type,
+            swizzle: swizzle(format),
+        };
+    }
+}
+
+struct LoadedTexture {
+    texture: Texture,
+    /// Target the texture is bound to (gl::TEXTURE_2D, gl::TEXTURE_CUBE_MAP, ...)
+    target: GLenum,
+}
 
 pub struct TextureLoader {
-    textures: HashMap<String, u32>,
+    textures: HashMap<String, LoadedTexture>,
 }
 
 impl TextureLoader {
@@ -17,51 +105,778 @@ impl TextureLoader {
         return texture_loader;
     }
 
-    pub fn load(&mut self, path: &Path, id: &str) -> bool {
-        if !path.exists() {
-            println!("Invalid texture path: {}", path.to_str().unwrap());
+    /// Load an image file, or a KTX2 or DDS file with `load_compressed`
+    pub fn load(&mut self, path: &Path, id: &str, desc: &TextureDesc) -> Result<()> {
+        if is_compressed_file(path) {
+            return self.load_compressed(path, id, desc);
+        }
+        let texture = read_image(path)?;
+
+        return self.load_image(texture, id, desc);
+    }
 
-            return false;
+    /// Upload the image in the format matching its pixel type. Images GL has
+    /// no format for are converted to 8-bit RGBA.
+    pub fn load_image(
+        &mut self,
+        texture: DynamicImage,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let (mut texture, format) = with_pixel_format(texture, desc.srgb);
+
+        if desc.flip_vertically {
+            texture = texture.flipv();
         }
 
-        let mut texture = image::open(path).expect(&format!(
-            "Failed to load texture: {}",
-            path.to_str().unwrap()
-        ));
-        let format = match texture {
-            DynamicImage::ImageRgb8(_) => gl::RGB,
-            DynamicImage::ImageRgba8(_) => gl::RGBA,
-            _ => gl::RGBA,
-        };
+        let texture_object = Texture::new(id);
 
-        /********************************************************
-         Texture (image file) is flipped upside down
-         because coordinate of image and 3D space are different.
-        ********************************************************/
-        texture = texture.flipv();
+        // Unsafe block to use some function of OpenGL
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture_object.id());
+            set_parameters(gl::TEXTURE_2D, desc, format.swizzle);
+            upload(gl::TEXTURE_2D, 0, &texture, &format);
+            if desc.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        self.insert(id, texture_object, gl::TEXTURE_2D);
 
-        let data = texture.as_bytes().to_vec();
-        let mut texture_id = 0;
+        return Ok(());
+    }
+
+    /// Texture from a KTX2 or DDS file of BC1-BC7 or ETC2 blocks, with the mipmaps
+    /// stored in the file. Formats the driver lacks are decompressed on the CPU,
+    /// except BC6H. The file's color space overrides `desc.srgb` if it has one.
+    /// Blocks cannot be flipped, so `flip_vertically` does not apply: store the
+    /// bottom row first, as texture tools do for OpenGL.
+    pub fn load_compressed(&mut self, path: &Path, id: &str, desc: &TextureDesc) -> Result<()> {
+        let image = CompressedImage::read(path)?;
+        let srgb = image.srgb.unwrap_or(desc.srgb);
+        let supported = image.format.is_supported(srgb);
+        let level_num = if desc.mipmaps { image.levels.len() } else { 1 };
+
+        // Decompress before creating the texture so that a failure leaves nothing behind
+        let decompressed = if supported {
+            Vec::new()
+        } else {
+            print_warning_log(&format!(
+                "{:?} is not supported by the driver, decompressing {}",
+                image.format,
+                path.display()
+            ));
+            let mut levels = Vec::with_capacity(level_num);
+            for level in 0..level_num {
+                levels.push(with_pixel_format(image.decompress(level)?, srgb));
+            }
+            levels
+        };
+        // Mipmaps can only be generated from decompressed levels
+        let generate = desc.mipmaps && level_num == 1 && !supported;
+        let desc = TextureDesc {
+            mipmaps: level_num > 1 || generate,
+            ..*desc
+        };
+
+        let texture_object = Texture::new(id);
 
         // Unsafe block to use some function of OpenGL
         unsafe {
-            gl::GenTextures(1, &mut texture_id);
-            gl::BindTexture(gl::TEXTURE_2D, texture_id);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-            gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, texture.width() as i32, texture.height() as i32, 0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void,);
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+            gl::BindTexture(gl::TEXTURE_2D, texture_object.id());
+            if supported {
+                set_parameters(gl::TEXTURE_2D, &desc, image.format.swizzle());
+                let internal_format = image.format.gl_internal_format(srgb);
+                for level in 0..level_num {
+                    let (width, height) = image.level_size(level);
+                    let data = image.level_data(level);
+                    gl::CompressedTexImage2D(
+                        gl::TEXTURE_2D,
+                        level as i32,
+                        internal_format,
+                        width as i32,
+                        height as i32,
+                        0,
+                        data.len() as i32,
+                        data.as_ptr() as *const c_void,
+                    );
+                }
+            } else {
+                set_parameters(gl::TEXTURE_2D, &desc, decompressed[0].1.swizzle);
+                for (level, (decoded, format)) in decompressed.iter().enumerate() {
+                    upload(gl::TEXTURE_2D, level as i32, decoded, format);
+                }
+            }
+            if generate {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            } else if level_num > 1 {
+                // The file may stop before the 1x1 level
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, level_num as i32 - 1);
+            }
             gl::BindTexture(gl::TEXTURE_2D, 0);
         }
 
-        self.textures.insert(id.to_string(), texture_id);
+        self.insert(id, texture_object, gl::TEXTURE_2D);
+
+        return Ok(());
+    }
+
+    /// Cubemap from a single image: an equirectangular panorama (2:1), or the
+    /// faces laid out as a horizontal (4:3) or vertical (3:4) cross
+    pub fn load_cubemap(&mut self, path: &Path, id: &str, desc: &TextureDesc) -> Result<()> {
+        let image = read_image(path)?;
+
+        return self
+            .load_cubemap_image(image, id, desc)
+            .map_err(|e| match e {
+                Error::Decode { message, .. } => Error::Decode {
+                    path: path.to_string_lossy().into_owned(),
+                    message,
+                },
+                e => e,
+            });
+    }
+
+    /// Cubemap from six images in the order +X, -X, +Y, -Y, +Z, -Z.
+    /// Cubemap faces start at the top row like images, so `flip_vertically` is ignored.
+    pub fn load_cubemap_faces(
+        &mut self,
+        faces: [&Path; 6],
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let mut images = Vec::with_capacity(faces.len());
+        for path in faces {
+            images.push(read_image(path)?);
+        }
+
+        return self.upload_cubemap(images, id, desc);
+    }
+
+    /// Cubemap from an equirectangular or cross layout image, see `load_cubemap`
+    pub fn load_cubemap_image(
+        &mut self,
+        image: DynamicImage,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let faces = match cubemap_faces(&image) {
+            Some(faces) => faces,
+            None => {
+                return Err(Error::Decode {
+                    path: id.to_string(),
+                    message: format!(
+                        "{}x{} is neither a 2:1 equirectangular nor a 4:3 or 3:4 cross cubemap",
+                        image.width(),
+                        image.height()
+                    ),
+                })
+            }
+        };
+
+        return self.upload_cubemap(faces, id, desc);
+    }
+
+    fn upload_cubemap(
+        &mut self,
+        faces: Vec<DynamicImage>,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let (width, height) = same_size(&faces, id)?;
+        if width != height {
+            return Err(Error::Decode {
+                path: id.to_string(),
+                message: "cubemap faces are not squares".to_string(),
+            });
+        }
+        let faces = same_pixel_type(faces);
+
+        let texture_object = Texture::new(id);
+
+        // Unsafe block to use some function of OpenGL
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture_object.id());
+            for (i, face) in faces.into_iter().enumerate() {
+                let (face, format) = with_pixel_format(face, desc.srgb);
+                if i == 0 {
+                    set_parameters(gl::TEXTURE_CUBE_MAP, desc, format.swizzle);
+                }
+                upload(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum,
+                    0,
+                    &face,
+                    &format,
+                );
+            }
+            if desc.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+            }
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        self.insert(id, texture_object, gl::TEXTURE_CUBE_MAP);
+
+        return Ok(());
+    }
+
+    /// 2D array texture with one layer per image, in order. The images must have the same size.
+    pub fn load_array(&mut self, paths: &[&Path], id: &str, desc: &TextureDesc) -> Result<()> {
+        let mut images = Vec::with_capacity(paths.len());
+        for path in paths {
+            images.push(read_image(path)?);
+        }
+
+        return self.load_array_images(images, id, desc);
+    }
+
+    /// 2D array texture from an image split into a grid of `columns` by `rows`
+    /// cells, such as a sprite sheet. Layers go left to right, then top to bottom.
+    pub fn load_array_grid(
+        &mut self,
+        path: &Path,
+        columns: u32,
+        rows: u32,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let image = read_image(path)?;
+        let layers = split_grid(&image, columns, rows).map_err(|message| Error::Decode {
+            path: path.to_string_lossy().into_owned(),
+            message,
+        })?;
+
+        return self.load_array_images(layers, id, desc);
+    }
+
+    /// 2D array texture with one layer per image, see `load_array`
+    pub fn load_array_images(
+        &mut self,
+        layers: Vec<DynamicImage>,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        if layers.is_empty() {
+            return Err(Error::Decode {
+                path: id.to_string(),
+                message: "array texture has no layers".to_string(),
+            });
+        }
+        let (width, height) = same_size(&layers, id)?;
+        let layers = same_pixel_type(layers);
+        let layer_num = layers.len() as i32;
+
+        let texture_object = Texture::new(id);
+
+        // Unsafe block to use some function of OpenGL
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, texture_object.id());
+            for (i, layer) in layers.into_iter().enumerate() {
+                let (mut layer, format) = with_pixel_format(layer, desc.srgb);
+                if desc.flip_vertically {
+                    layer = layer.flipv();
+                }
+                if i == 0 {
+                    set_parameters(gl::TEXTURE_2D_ARRAY, desc, format.swizzle);
+                    upload_3d(
+                        gl::TEXTURE_2D_ARRAY,
+                        [width, height, layer_num as u32],
+                        &format,
+                        ptr::null(),
+                    );
+                }
+
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                gl::TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    i as i32,
+                    width as i32,
+                    height as i32,
+                    1,
+                    format.format,
+                    format.data_type,
+                    layer.as_bytes().as_ptr() as *const c_void,
+                );
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            }
+            if desc.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            }
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        self.insert(id, texture_object, gl::TEXTURE_2D_ARRAY);
+
+        return Ok(());
+    }
+
+    /// 3D texture from a raw volume file of `size` voxels without a header.
+    /// `desc.srgb` and `desc.flip_vertically` do not apply to volumes.
+    pub fn load_volume(
+        &mut self,
+        path: &Path,
+        size: [u32; 3],
+        format: VolumeFormat,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        let data = resources::read(path)?;
+
+        return self
+            .load_volume_data(&data, size, format, id, desc)
+            .map_err(|e| match e {
+                Error::Decode { message, .. } => Error::Decode {
+                    path: path.to_string_lossy().into_owned(),
+                    message,
+                },
+                e => e,
+            });
+    }
+
+    /// 3D texture from raw voxels, see `load_volume`
+    pub fn load_volume_data(
+        &mut self,
+        data: &[u8],
+        size: [u32; 3],
+        format: VolumeFormat,
+        id: &str,
+        desc: &TextureDesc,
+    ) -> Result<()> {
+        check_volume_size(data.len(), size, format).map_err(|message| Error::Decode {
+            path: id.to_string(),
+            message,
+        })?;
+        let format = format.pixel_format();
+
+        let texture_object = Texture::new(id);
+
+        // Unsafe block to use some function of OpenGL
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_3D, texture_object.id());
+            set_parameters(gl::TEXTURE_3D, desc, format.swizzle);
+            upload_3d(
+                gl::TEXTURE_3D,
+                size,
+                &format,
+                data.as_ptr() as *const c_void,
+            );
+            if desc.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_3D);
+            }
+            gl::BindTexture(gl::TEXTURE_3D, 0);
+        }
+
+        self.insert(id, texture_object, gl::TEXTURE_3D);
+
+        return Ok(());
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        return self.textures.contains_key(id);
+    }
+
+    pub fn get_from_id(&self, id: &str) -> Result<u32> {
+        return match self.textures.get(id) {
+            Some(loaded) => Ok(loaded.texture.id()),
+            None => Err(unknown_texture(id)),
+        };
+    }
+
+    /// Target to bind the texture to (gl::TEXTURE_2D, gl::TEXTURE_2D_ARRAY, ...)
+    pub fn get_target(&self, id: &str) -> Result<GLenum> {
+        return match self.textures.get(id) {
+            Some(loaded) => Ok(loaded.target),
+            None => Err(unknown_texture(id)),
+        };
+    }
+
+    /// Ids of the loaded textures with their targets, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, GLenum)> {
+        return self
+            .textures
+            .iter()
+            .map(|(id, loaded)| (id.as_str(), loaded.target));
+    }
+
+    pub fn unload(&mut self, id: &str) -> Result<()> {
+        return match self.textures.remove(id) {
+            Some(_) => Ok(()),
+            None => Err(unknown_texture(id)),
+        };
+    }
+
+    /// Delete all textures
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+
+    fn insert(&mut self, id: &str, texture: Texture, target: GLenum) {
+        // Replacing a texture with the same id deletes the old one
+        self.textures
+            .insert(id.to_string(), LoadedTexture { texture, target });
+    }
+}
+
+fn read_image(path: &Path) -> Result<DynamicImage> {
+    let data = resources::read(path)?;
+    let image = match ImageFormat::from_path(path) {
+        Ok(format) => image::load_from_memory_with_format(&data, format),
+        Err(_) => image::load_from_memory(&data),
+    };
+
+    return match image {
+        Ok(image) => Ok(image),
+        Err(ImageError::IoError(e)) => Err(Error::io(path, e)),
+        Err(e) => Err(Error::Decode {
+            path: path.to_string_lossy().into_owned(),
+            message: e.to_string(),
+        }),
+    };
+}
+
+/// The image with its pixel format, converted to 8-bit RGBA if GL has no format for it
+fn with_pixel_format(image: DynamicImage, srgb: bool) -> (DynamicImage, PixelFormat) {
+    return match pixel_format(&image, srgb) {
+        Some(format) => (image, format),
+        None => {
+            let image = DynamicImage::ImageRgba8(image.to_rgba8());
+            let format = pixel_format(&image, srgb).unwrap();
+            (image, format)
+        }
+    };
+}
+
+/// Sampling parameters and swizzle of the texture bound to `target`
+unsafe fn set_parameters(target: GLenum, desc: &TextureDesc, swizzle: [GLenum; 4]) {
+    if desc.mipmaps {
+        desc.sampler.apply_to_texture(target);
+    } else {
+        desc.sampler.without_mipmaps().apply_to_texture(target);
+        // Complete without mipmaps even when a sampler object asks for them
+        gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, 0);
+    }
+
+    let swizzle = swizzle.map(|channel| channel as GLint);
+    gl::TexParameteriv(target, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+}
+
+/// Cells of a `columns` by `rows` grid, left to right, then top to bottom
+fn split_grid(
+    image: &DynamicImage,
+    columns: u32,
+    rows: u32,
+) -> std::result::Result<Vec<DynamicImage>, String> {
+    if columns == 0
+        || rows == 0
+        || !image.width().is_multiple_of(columns)
+        || !image.height().is_multiple_of(rows)
+    {
+        return Err(format!(
+            "{}x{} does not split into {}x{} cells",
+            image.width(),
+            image.height(),
+            columns,
+            rows
+        ));
+    }
+
+    let (width, height) = (image.width() / columns, image.height() / rows);
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            cells.push(image.crop_imm(column * width, row * height, width, height));
+        }
+    }
+
+    return Ok(cells);
+}
+
+/// Raw voxels must fill the volume exactly
+fn check_volume_size(
+    byte_num: usize,
+    size: [u32; 3],
+    format: VolumeFormat,
+) -> std::result::Result<(), String> {
+    let expected = size.iter().map(|n| *n as usize).product::<usize>() * format.bytes_per_voxel();
+    if byte_num != expected {
+        return Err(format!(
+            "{} bytes do not hold {}x{}x{} {:?} voxels ({} bytes)",
+            byte_num, size[0], size[1], size[2], format, expected
+        ));
+    }
+
+    return Ok(());
+}
+
+/// Size shared by all the images
+fn same_size(images: &[DynamicImage], id: &str) -> Result<(u32, u32)> {
+    let size = (images[0].width(), images[0].height());
+    if images
+        .iter()
+        .any(|image| (image.width(), image.height()) != size)
+    {
+        return Err(Error::Decode {
+            path: id.to_string(),
+            message: "images are not all the same size".to_string(),
+        });
+    }
+
+    return Ok(size);
+}
+
+/// Images of a texture share one format, so mixed pixel types become 8-bit RGBA
+fn same_pixel_type(images: Vec<DynamicImage>) -> Vec<DynamicImage> {
+    let color = images[0].color();
+    if images.iter().all(|image| image.color() == color) {
+        return images;
+    }
+
+    return images
+        .into_iter()
+        .map(|image| DynamicImage::ImageRgba8(image.to_rgba8()))
+        .collect();
+}
+
+/// Allocate the base level of a 3D or array texture bound to `target`,
+/// filled from `data` if it is not null
+unsafe fn upload_3d(target: GLenum, size: [u32; 3], format: &PixelFormat, data: *const c_void) {
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexImage3D(
+        target,
+        0,
+        format.internal_format as i32,
+        size[0] as i32,
+        size[1] as i32,
+        size[2] as i32,
+        0,
+        format.format,
+        format.data_type,
+        data,
+    );
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+}
+
+/// Upload a level of `target`, a 2D texture or a cubemap face
+unsafe fn upload(target: GLenum, level: i32, image: &DynamicImage, format: &PixelFormat) {
+    // Rows are tightly packed, which the default 4-byte alignment breaks
+    // for e.g. RGB images with an odd width
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexImage2D(
+        target,
+        level,
+        format.internal_format as i32,
+        image.width() as i32,
+        image.height() as i32,
+        0,
+        format.format,
+        format.data_type,
+        image.as_bytes().as_ptr() as *const c_void,
+    );
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+}
+
+/// Faces in the order +X, -X, +Y, -Y, +Z, -Z of an equirectangular or cross layout image
+fn cubemap_faces(image: &DynamicImage) -> Option<Vec<DynamicImage>> {
+    let (width, height) = (image.width(), image.height());
+
+    if width == 2 * height {
+        return Some(equirectangular_faces(image));
+    }
+
+    // Cell of each face in the cross, in units of the face size
+    let (size, cells) = if 3 * width == 4 * height {
+        (width / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)])
+    } else if 4 * width == 3 * height {
+        (width / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)])
+    } else {
+        return None;
+    };
+    if size == 0 {
+        return None;
+    }
+
+    let mut faces: Vec<DynamicImage> = cells
+        .iter()
+        .map(|(x, y)| image.crop_imm(x * size, y * size, size, size))
+        .collect();
+    if height > width {
+        // -Z hangs below -Y in the vertical cross, so it is upside down
+        faces[5] = faces[5].rotate180();
+    }
+
+    return Some(faces);
+}
+
+/// Resample an equirectangular panorama (longitude along x, +Y at the top row)
+/// into six faces a quarter of its width, keeping its bit depth
+fn equirectangular_faces(image: &DynamicImage) -> Vec<DynamicImage> {
+    let source = image.to_rgba32f();
+    let size = (image.width() / 4).max(1);
+
+    return (0..6)
+        .map(|face| {
+            let mut output = image::Rgba32FImage::new(size, size);
+            for (x, y, pixel) in output.enumerate_pixels_mut() {
+                // Position on the face from -1 to 1, s to the right and t down
+                let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                let direction = match face {
+                    0 => [1.0, -t, -s],
+                    1 => [-1.0, -t, s],
+                    2 => [s, 1.0, t],
+                    3 => [s, -1.0, -t],
+                    4 => [s, -t, 1.0],
+                    _ => [-s, -t, -1.0],
+                };
+                let length = (direction[0] * direction[0]
+                    + direction[1] * direction[1]
+                    + direction[2] * direction[2])
+                    .sqrt();
+
+                // -Z is the center of the panorama
+                let u = 0.5 + direction[0].atan2(-direction[2]) / (2.0 * PI);
+                let v = (direction[1] / length).clamp(-1.0, 1.0).acos() / PI;
+                *pixel = sample_bilinear(&source, u, v);
+            }
+
+            let output = DynamicImage::ImageRgba32F(output);
+            match image.color().bytes_per_pixel() / image.color().channel_count() {
+                1 => DynamicImage::ImageRgba8(output.to_rgba8()),
+                2 => DynamicImage::ImageRgba16(output.to_rgba16()),
+                _ => output,
+            }
+        })
+        .collect();
+}
+
+/// Bilinear sample at `u`, `v` from 0 to 1, wrapping horizontally and clamping vertically
+fn sample_bilinear(image: &image::Rgba32FImage, u: f32, v: f32) -> image::Rgba<f32> {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: i64, y: i64| {
+        return image
+            .get_pixel(x.rem_euclid(width) as u32, y.clamp(0, height - 1) as u32)
+            .0;
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = [texel(x0, y0), texel(x0 + 1, y0)];
+    let bottom = [texel(x0, y0 + 1), texel(x0 + 1, y0 + 1)];
+
+    let mut color = [0.0; 4];
+    for (channel, value) in color.iter_mut().enumerate() {
+        let top = top[0][channel] * (1.0 - fx) + top[1][channel] * fx;
+        let bottom = bottom[0][channel] * (1.0 - fx) + bottom[1][channel] * fx;
+        *value = top * (1.0 - fy) + bottom * fy;
+    }
+
+    return image::Rgba(color);
+}
+
+/// How the pixels of an image are uploaded
+struct PixelFormat {
+    internal_format: GLenum,
+    format: GLenum,
+    data_type: GLenum,
+    /// Source channel of red, green, blue and alpha when sampled
+    swizzle: [GLenum; 4],
+}
+
+/// Format matching the pixel type of the image, if GL has one.
+/// `srgb` only applies to 8-bit RGB and RGBA, the only sRGB formats.
+fn pixel_format(image: &DynamicImage, srgb: bool) -> Option<PixelFormat> {
+    let (internal_format, format, data_type) = match image {
+        DynamicImage::ImageLuma8(_) => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageLumaA8(_) => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageRgb8(_) if srgb => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageRgb8(_) => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageRgba8(_) if srgb => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageRgba8(_) => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+        DynamicImage::ImageLuma16(_) => (gl::R16, gl::RED, gl::UNSIGNED_SHORT),
+        DynamicImage::ImageLumaA16(_) => (gl::RG16, gl::RG, gl::UNSIGNED_SHORT),
+        DynamicImage::ImageRgb16(_) => (gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT),
+        DynamicImage::ImageRgba16(_) => (gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT),
+        DynamicImage::ImageRgb32F(_) => (gl::RGB32F, gl::RGB, gl::FLOAT),
+        DynamicImage::ImageRgba32F(_) => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+        _ => return None,
+    };
+
+    return Some(PixelFormat {
+        internal_format,
+        format,
+        data_type,
+        swizzle: swizzle(format),
+    });
+}
+
+/// Gray is stored in red and alpha in green, so spread them back when sampled
+fn swizzle(format: GLenum) -> [GLenum; 4] {
+    return match format {
+        gl::RED => [gl::RED, gl::RED, gl::RED, gl::ONE],
+        gl::RG => [gl::RED, gl::RED, gl::RED, gl::GREEN],
+        _ => [gl::RED, gl::GREEN, gl::BLUE, gl::ALPHA],
+    };
+}
+
+fn unknown_texture(id: &str) -> Error {
+    return Error::UnknownResource {
+        kind: "texture",
+        id: id.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn grid_layers_go_left_to_right_then_top_to_bottom() {
+        // 3x2 cells of 2x2 pixels, each filled with its cell index
+        let image = RgbImage::from_fn(6, 4, |x, y| {
+            let cell = (y / 2 * 3 + x / 2) as u8;
+            Rgb([cell, 0, 0])
+        });
+        let cells = split_grid(&DynamicImage::ImageRgb8(image), 3, 2).unwrap();
+
+        assert_eq!(cells.len(), 6);
+        for (layer, cell) in cells.iter().enumerate() {
+            let cell = cell.to_rgb8();
+            assert_eq!(cell.dimensions(), (2, 2));
+            assert!(cell.pixels().all(|pixel| pixel[0] == layer as u8));
+        }
+    }
+
+    #[test]
+    fn grid_must_divide_the_image() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(6, 4));
 
-        return true;
+        assert_eq!(
+            split_grid(&image, 4, 2).err().unwrap(),
+            "6x4 does not split into 4x2 cells"
+        );
+        assert!(split_grid(&image, 0, 1).is_err());
+        assert!(split_grid(&image, 6, 4).is_ok());
     }
 
-    pub fn get_from_id(&mut self, id: &str) -> u32 {
-        return *self.textures.get(id).expect("Failed to get loaded texture");
+    #[test]
+    fn volume_data_must_fill_the_volume() {
+        assert!(check_volume_size(2 * 3 * 4, [2, 3, 4], VolumeFormat::R8).is_ok());
+        assert!(check_volume_size(2 * 3 * 4 * 2, [2, 3, 4], VolumeFormat::R16).is_ok());
+        assert!(check_volume_size(2 * 3 * 4 * 4, [2, 3, 4], VolumeFormat::Rgba8).is_ok());
+        assert_eq!(
+            check_volume_size(95, [2, 3, 4], VolumeFormat::R32F)
+                .err()
+                .unwrap(),
+            "95 bytes do not hold 2x3x4 R32F voxels (96 bytes)"
+        );
     }
 }
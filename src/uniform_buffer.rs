@@ -0,0 +1,297 @@
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use core::ffi::c_void;
+use std::marker::PhantomData;
+
+use crate::gl_object::Buffer;
+
+/// Type with a std140 layout. Usually derived with `#[derive(Std140)]` on a struct
+/// mirroring a GLSL uniform block.
+pub trait Std140 {
+    /// Base alignment in bytes
+    const ALIGN: usize;
+    /// Size in bytes, including trailing padding
+    const SIZE: usize;
+
+    /// Write the value to the beginning of `out`, which is at least `SIZE` bytes
+    fn write_std140(&self, out: &mut [u8]);
+}
+
+/// Round `offset` up to a multiple of `align`
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    return offset.div_ceil(align) * align;
+}
+
+macro_rules! impl_std140_scalar {
+    ($($type:ty),*) => {
+        $(
+            impl Std140 for $type {
+                const ALIGN: usize = 4;
+                const SIZE: usize = 4;
+
+                fn write_std140(&self, out: &mut [u8]) {
+                    out[..4].copy_from_slice(&self.to_ne_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_std140_scalar!(f32, i32, u32);
+
+impl Std140 for bool {
+    const ALIGN: usize = 4;
+    const SIZE: usize = 4;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        (*self as u32).write_std140(out);
+    }
+}
+
+macro_rules! impl_std140_vector {
+    ($($type:ident => $align:expr, $size:expr, [$($field:ident),*]),*) => {
+        $(
+            impl Std140 for $type<f32> {
+                const ALIGN: usize = $align;
+                const SIZE: usize = $size;
+
+                fn write_std140(&self, out: &mut [u8]) {
+                    for (index, component) in [$(self.$field),*].iter().enumerate() {
+                        component.write_std140(&mut out[index * 4..]);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// A vec3 is aligned like a vec4 but only takes 12 bytes
+impl_std140_vector!(
+    Vector2 => 8, 8, [x, y],
+    Vector3 => 16, 12, [x, y, z],
+    Vector4 => 16, 16, [x, y, z, w]
+);
+
+// Matrices are stored as arrays of column vectors, each padded to a vec4
+impl Std140 for Matrix3<f32> {
+    const ALIGN: usize = 16;
+    const SIZE: usize = 48;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        self.x.write_std140(&mut out[0..]);
+        self.y.write_std140(&mut out[16..]);
+        self.z.write_std140(&mut out[32..]);
+    }
+}
+
+impl Std140 for Matrix4<f32> {
+    const ALIGN: usize = 16;
+    const SIZE: usize = 64;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        self.x.write_std140(&mut out[0..]);
+        self.y.write_std140(&mut out[16..]);
+        self.z.write_std140(&mut out[32..]);
+        self.w.write_std140(&mut out[48..]);
+    }
+}
+
+// Array elements are padded to a multiple of a vec4
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = align_to(T::ALIGN, 16);
+    const SIZE: usize = align_to(T::SIZE, 16) * N;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        let stride = align_to(T::SIZE, 16);
+        for (index, element) in self.iter().enumerate() {
+            element.write_std140(&mut out[index * stride..]);
+        }
+    }
+}
+
+/// Uniform buffer holding one `T`, bound to a fixed binding point.
+/// Programs read it after `Shader::set_uniform_block` attaches a block to the binding point.
+pub struct UniformBuffer<T: Std140> {
+    ubo: Buffer,
+    binding: u32,
+    /// Staging memory for the std140 representation
+    data: Vec<u8>,
+    value_type: PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+    pub fn new(label: &str, binding: u32, value: &T) -> UniformBuffer<T> {
+        let mut uniform_buffer = UniformBuffer {
+            ubo: Buffer::new(label),
+            binding,
+            data: vec![0; T::SIZE],
+            value_type: PhantomData,
+        };
+        value.write_std140(&mut uniform_buffer.data);
+
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, uniform_buffer.ubo.id());
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                T::SIZE as isize,
+                uniform_buffer.data.as_ptr() as *const c_void,
+                gl::DYNAMIC_DRAW,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+
+            uniform_buffer.bind();
+        }
+
+        return uniform_buffer;
+    }
+
+    /// Replace the whole value
+    pub fn update(&mut self, value: &T) {
+        value.write_std140(&mut self.data);
+
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo.id());
+            gl::BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                T::SIZE as isize,
+                self.data.as_ptr() as *const c_void,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+
+    /// Bind the buffer to its binding point again (e.g. after another buffer used it)
+    pub unsafe fn bind(&self) {
+        gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.ubo.id());
+    }
+
+    pub fn binding(&self) -> u32 {
+        return self.binding;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, Vector2, Vector3};
+    use rust3d_derive::Std140;
+
+    use super::*;
+    use crate::camera::Camera;
+    use crate::light::Light;
+
+    #[derive(Std140)]
+    struct Inner {
+        x: f32,
+        y: Vector3<f32>,
+    }
+
+    #[derive(Std140)]
+    struct Mixed {
+        a: f32,
+        b: Vector3<f32>,
+        c: f32,
+        d: [f32; 3],
+        e: Vector2<f32>,
+        f: Inner,
+        g: Matrix4<f32>,
+        h: bool,
+    }
+
+    fn std140_bytes<T: Std140>(value: &T) -> Vec<u8> {
+        let mut data = vec![0; T::SIZE];
+        value.write_std140(&mut data);
+
+        return data;
+    }
+
+    fn read_f32(data: &[u8], offset: usize) -> f32 {
+        return f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        return u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    }
+
+    #[test]
+    fn camera_layout() {
+        let camera = Camera {
+            view: Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)),
+            projection: Matrix4::from_scale(5.0),
+            position: Vector3::new(6.0, 7.0, 8.0),
+        };
+        let data = std140_bytes(&camera);
+
+        // mat4 view at 0, mat4 projection at 64, vec3 position at 128
+        assert_eq!(Camera::SIZE, 144);
+        assert_eq!(read_f32(&data, 48), 1.0);
+        assert_eq!(read_f32(&data, 56), 3.0);
+        assert_eq!(read_f32(&data, 60), 1.0);
+        assert_eq!(read_f32(&data, 64), 5.0);
+        assert_eq!(read_f32(&data, 84), 5.0);
+        assert_eq!(read_f32(&data, 128), 6.0);
+        assert_eq!(read_f32(&data, 136), 8.0);
+    }
+
+    #[test]
+    fn light_layout() {
+        let light = Light {
+            ambient: Vector3::new(1.0, 1.5, 1.75),
+            diffuse: Vector3::new(2.0, 2.5, 2.75),
+            direction: Vector3::new(3.0, 3.5, 3.75),
+            specular: Vector3::new(4.0, 4.5, 4.75),
+        };
+        let data = std140_bytes(&light);
+
+        // Each vec3 starts on a 16-byte boundary
+        assert_eq!(Light::SIZE, 64);
+        for (index, value) in [1.0, 2.0, 3.0, 4.0].iter().enumerate() {
+            assert_eq!(read_f32(&data, index * 16), *value);
+            assert_eq!(read_f32(&data, index * 16 + 8), *value + 0.75);
+        }
+    }
+
+    #[test]
+    fn mixed_layout() {
+        let mixed = Mixed {
+            a: 1.0,
+            b: Vector3::new(2.0, 3.0, 4.0),
+            c: 5.0,
+            d: [6.0, 7.0, 8.0],
+            e: Vector2::new(9.0, 10.0),
+            f: Inner {
+                x: 11.0,
+                y: Vector3::new(12.0, 13.0, 14.0),
+            },
+            g: Matrix4::from_scale(15.0),
+            h: true,
+        };
+        let data = std140_bytes(&mixed);
+
+        assert_eq!(Inner::ALIGN, 16);
+        assert_eq!(Inner::SIZE, 32);
+        assert_eq!(Mixed::ALIGN, 16);
+        assert_eq!(Mixed::SIZE, 208);
+
+        assert_eq!(read_f32(&data, 0), 1.0);
+        // vec3 is aligned to 16
+        assert_eq!(read_f32(&data, 16), 2.0);
+        assert_eq!(read_f32(&data, 24), 4.0);
+        // A float after a vec3 fills its last 4 bytes
+        assert_eq!(read_f32(&data, 28), 5.0);
+        // Array elements are padded to 16 bytes
+        assert_eq!(read_f32(&data, 32), 6.0);
+        assert_eq!(read_f32(&data, 48), 7.0);
+        assert_eq!(read_f32(&data, 64), 8.0);
+        assert_eq!(read_f32(&data, 80), 9.0);
+        assert_eq!(read_f32(&data, 84), 10.0);
+        // Nested structs start on a 16-byte boundary and keep their own layout
+        assert_eq!(read_f32(&data, 96), 11.0);
+        assert_eq!(read_f32(&data, 112), 12.0);
+        assert_eq!(read_f32(&data, 120), 14.0);
+        // mat4 columns are consecutive vec4s
+        assert_eq!(read_f32(&data, 128), 15.0);
+        assert_eq!(read_f32(&data, 148), 15.0);
+        assert_eq!(read_f32(&data, 188), 1.0);
+        assert_eq!(read_u32(&data, 192), 1);
+    }
+}
@@ -0,0 +1,27 @@
+//! Names of the uniforms and uniform blocks set from Rust code.
+//! The shader validation tests check that the shaders declare every one of them.
+
+use std::ffi::CStr;
+
+/// Model matrix, set by `Mesh::draw` and for the normal lines
+pub const MODEL: &CStr = c"uModel";
+/// Opacity multiplied with the material alpha
+pub const ALPHA: &CStr = c"uAlpha";
+/// `struct Material` in Lighting.glsl, set by `Material::apply`
+pub const MATERIAL: &CStr = c"uMaterial";
+pub const DIFFUSE_TEXTURE: &CStr = c"uScreenTexture";
+pub const NORMAL_TEXTURE: &CStr = c"uNormalTexture";
+pub const ENVIRONMENT_MAP: &CStr = c"uEnvironmentMap";
+/// Length of the lines drawn by Normals.geom
+pub const NORMAL_LENGTH: &CStr = c"uNormalLength";
+pub const SKYBOX: &CStr = c"uSkybox";
+
+/// Particles.vert, and Particles.comp with the simulation uniforms below
+pub const PARTICLE_LIFETIME: &CStr = c"uLifetime";
+pub const PARTICLE_DELTA: &CStr = c"uDelta";
+pub const PARTICLE_LAUNCH_SPEED: &CStr = c"uLaunchSpeed";
+pub const PARTICLE_SPREAD: &CStr = c"uSpread";
+pub const PARTICLE_GRAVITY: &CStr = c"uGravity";
+
+pub const CAMERA_BLOCK: &CStr = c"Camera";
+pub const LIGHT_BLOCK: &CStr = c"Light";
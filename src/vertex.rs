@@ -1,54 +1,211 @@
-use gl::types::{GLenum, GLfloat, GLint, GLsizei, GLsizeiptr};
+use gl::types::{GLenum, GLintptr, GLsizei, GLsizeiptr, GLsync};
+use rust3d_derive::VertexFormat;
 
 use core::ffi::c_void;
 use std::mem;
+use std::ptr;
+
+use crate::common::{gl_supports, print_warning_log};
+use crate::gl_object::{Buffer, VertexArray};
+
+/// Scalar, vector or matrix that can be a field of a vertex format
+pub trait AttributeType {
+    const GL_TYPE: GLenum;
+    const COMPONENTS: i32;
+    /// Matrices take one attribute location per column
+    const LOCATIONS: u32 = 1;
+}
+
+macro_rules! impl_attribute_type {
+    ($($type:ty => $gl_type:expr),*) => {
+        $(
+            impl AttributeType for $type {
+                const GL_TYPE: GLenum = $gl_type;
+                const COMPONENTS: i32 = 1;
+            }
+
+            impl<const N: usize> AttributeType for [$type; N] {
+                const GL_TYPE: GLenum = $gl_type;
+                const COMPONENTS: i32 = N as i32;
+            }
+
+            impl<const N: usize, const M: usize> AttributeType for [[$type; N]; M] {
+                const GL_TYPE: GLenum = $gl_type;
+                const COMPONENTS: i32 = N as i32;
+                const LOCATIONS: u32 = M as u32;
+            }
+        )*
+    };
+}
+
+impl_attribute_type!(
+    f32 => gl::FLOAT,
+    f64 => gl::DOUBLE,
+    i8 => gl::BYTE,
+    u8 => gl::UNSIGNED_BYTE,
+    i16 => gl::SHORT,
+    u16 => gl::UNSIGNED_SHORT,
+    i32 => gl::INT,
+    u32 => gl::UNSIGNED_INT
+);
+
+macro_rules! impl_cgmath_attribute_type {
+    ($($type:ty => $components:expr, $locations:expr),*) => {
+        $(
+            impl AttributeType for $type {
+                const GL_TYPE: GLenum = gl::FLOAT;
+                const COMPONENTS: i32 = $components;
+                const LOCATIONS: u32 = $locations;
+            }
+        )*
+    };
+}
+
+impl_cgmath_attribute_type!(
+    cgmath::Vector2<f32> => 2, 1,
+    cgmath::Vector3<f32> => 3, 1,
+    cgmath::Vector4<f32> => 4, 1,
+    cgmath::Matrix3<f32> => 3, 3,
+    cgmath::Matrix4<f32> => 4, 4
+);
+
+/// Layout of one attribute in a vertex format
+pub struct VertexAttribute {
+    pub location: u32,
+    pub gl_type: GLenum,
+    pub components: i32,
+    /// Number of consecutive locations used (columns of a matrix)
+    pub locations: u32,
+    /// Map integer values to [0, 1] (unsigned) or [-1, 1] (signed) floats
+    pub normalized: bool,
+    /// Offset in bytes from the start of the vertex
+    pub offset: usize,
+}
+
+/// `#[repr(C)]` vertex struct whose fields are vertex attributes.
+/// Usually implemented by `#[derive(VertexFormat)]`.
+pub trait VertexFormat: Copy {
+    fn attributes() -> Vec<VertexAttribute>;
+}
+
+/// Vertex layout of Basic.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, VertexFormat)]
+pub struct BasicVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+}
+
+/// Vertex layout of Skybox.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, VertexFormat)]
+pub struct SkyboxVertex {
+    pub position: [f32; 3],
+}
+
+/// Vertex layout of Particles.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, VertexFormat)]
+pub struct Particle {
+    /// Position, and the age in seconds as w
+    pub position: [f32; 4],
+    /// Launch velocity, and the number of launches so far as w
+    pub velocity: [f32; 4],
+}
+
+/// Per-instance layout of Basic.vert with the `INSTANCED` feature
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, VertexFormat)]
+pub struct ModelInstance {
+    #[vertex(location = 3)]
+    pub model: cgmath::Matrix4<f32>,
+}
+
+/// Index data for `glDrawElements`
+pub enum IndexData<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+/// How `Vertex::stream` uploads a new set of vertices every frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamMode {
+    /// Orphan the buffer storage with `glBufferData(NULL)` and write into the new one
+    Orphan,
+    /// Write into a persistently mapped ring of buffer regions guarded by fences.
+    /// Falls back to `Orphan` when `glBufferStorage` (GL 4.4) is not available.
+    Persistent,
+}
+
+/// Number of regions written in turn by persistent streaming
+const STREAM_REGION_NUM: usize = 3;
+
+/// Buffer of per-instance attributes advanced once per instance
+struct InstanceBuffer {
+    vbo: Buffer,
+    stride: usize,
+    capacity: usize,
+}
+
+struct PersistentMapping {
+    pointer: *mut u8,
+    fences: [GLsync; STREAM_REGION_NUM],
+    region: usize,
+}
+
+impl Drop for PersistentMapping {
+    fn drop(&mut self) {
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            for fence in self.fences.iter().filter(|fence| !fence.is_null()) {
+                gl::DeleteSync(*fence);
+            }
+        }
+    }
+}
 
 pub struct Vertex {
-    vao: u32,
-    _vbo: u32,
+    vao: VertexArray,
+    vbo: Buffer,
+    ebo: Option<Buffer>,
+    mode: GLenum,
+    /// Size of one vertex in bytes
+    stride: usize,
+    /// Number of vertices the buffer (or one streaming region) can hold
+    capacity: usize,
     vertex_num: i32,
+    base_vertex: i32,
+    index_type: GLenum,
+    index_num: i32,
+    stream_mode: Option<StreamMode>,
+    mapping: Option<PersistentMapping>,
+    instance_buffers: Vec<InstanceBuffer>,
+    /// Number of instances in the instance buffers
+    instance_num: i32,
 }
 
 impl Vertex {
-    pub fn new(
-        size: GLsizeiptr,
-        data: *const c_void,
-        usage: GLenum,
-        attr_types: std::vec::Vec<GLenum>,
-        attr_sizes: std::vec::Vec<GLint>,
-        stride: GLsizei,
-        vertex_num: i32,
-    ) -> Vertex {
-        let mut vao = 0;
-        let mut _vbo = 0;
+    pub fn new<V: VertexFormat>(vertices: &[V], usage: GLenum) -> Vertex {
+        // Generate vertex array object and vertex buffer object
+        let vao = VertexArray::new("Vertex");
+        let vbo = Buffer::new("Vertex");
 
         // Use unsafe block to use OpenGL functions
         unsafe {
-            // Generate vertex array object and vertex buffer object
-            gl::GenVertexArrays(1, &mut vao);
-            gl::GenBuffers(1, &mut _vbo);
-
             // Bind array buffer
-            gl::BindVertexArray(vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, _vbo);
+            gl::BindVertexArray(vao.id());
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
 
             // Transfer vertex data 1st time
-            gl::BufferData(gl::ARRAY_BUFFER, size, data, usage);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                mem::size_of_val(vertices) as GLsizeiptr,
+                vertices.as_ptr() as *const c_void,
+                usage,
+            );
 
-            let mut offset = 0;
-
-            for i in 0..attr_types.len() {
-                gl::EnableVertexAttribArray(i as u32);
-                gl::VertexAttribPointer(
-                    i as u32,
-                    attr_sizes[i],
-                    attr_types[i],
-                    gl::FALSE,
-                    stride,
-                    (offset * mem::size_of::<GLfloat>()) as *const c_void,
-                );
-                offset += attr_sizes[i] as usize;
-            }
+            set_attribute_pointers::<V>(0);
 
             // Unbind buffer
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
@@ -57,17 +214,464 @@ impl Vertex {
 
         Vertex {
             vao,
-            _vbo,
-            vertex_num,
+            vbo,
+            ebo: None,
+            mode: gl::TRIANGLES,
+            stride: mem::size_of::<V>(),
+            capacity: vertices.len(),
+            vertex_num: vertices.len() as i32,
+            base_vertex: 0,
+            index_type: gl::UNSIGNED_INT,
+            index_num: 0,
+            stream_mode: None,
+            mapping: None,
+            instance_buffers: Vec::new(),
+            instance_num: 0,
+        }
+    }
+
+    /// Create empty vertex for data replaced every frame by `stream`.
+    /// `capacity` is at least 1 since immutable storage cannot be empty.
+    pub fn new_streaming<V: VertexFormat>(capacity: usize, mode: StreamMode) -> Vertex {
+        let capacity = capacity.max(1);
+        let mut vertex = Vertex::new::<V>(&[], gl::STREAM_DRAW);
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            if mode == StreamMode::Persistent && supports_buffer_storage() {
+                vertex.allocate_persistent::<V>(capacity);
+                vertex.stream_mode = Some(StreamMode::Persistent);
+            } else {
+                gl::BindBuffer(gl::ARRAY_BUFFER, vertex.vbo.id());
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    (capacity * vertex.stride) as GLsizeiptr,
+                    ptr::null(),
+                    gl::STREAM_DRAW,
+                );
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+                vertex.capacity = capacity;
+                vertex.stream_mode = Some(StreamMode::Orphan);
+            }
+        }
+
+        return vertex;
+    }
+
+    /// Overwrite vertices from `first` without changing the number of vertices.
+    /// Vertices past the end are left out.
+    pub fn update<V: VertexFormat>(&mut self, first: usize, vertices: &[V]) {
+        self.check_format::<V>();
+
+        let vertex_num = self.vertex_num as usize;
+        let count = vertices.len().min(vertex_num.saturating_sub(first));
+        if count < vertices.len() {
+            print_warning_log(&format!(
+                "Vertex update {}..{} is clipped to {} vertices",
+                first,
+                first + vertices.len(),
+                vertex_num
+            ));
+        }
+        let vertices = &vertices[..count];
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            if self.mapping.is_some() {
+                // Draw calls in flight may still read the current region
+                self.finish_region();
+
+                let mapping = self.mapping.as_ref().unwrap();
+                let offset = (mapping.region * self.capacity + first) * self.stride;
+                ptr::copy_nonoverlapping(
+                    vertices.as_ptr() as *const u8,
+                    mapping.pointer.add(offset),
+                    mem::size_of_val(vertices),
+                );
+            } else {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo.id());
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    (first * self.stride) as GLintptr,
+                    mem::size_of_val(vertices) as GLsizeiptr,
+                    vertices.as_ptr() as *const c_void,
+                );
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+        }
+    }
+
+    /// Replace all vertices without waiting for draw calls that still use the old ones
+    pub fn stream<V: VertexFormat>(&mut self, vertices: &[V]) {
+        self.check_format::<V>();
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            if self.mapping.is_some() {
+                if vertices.len() > self.capacity {
+                    self.allocate_persistent::<V>(vertices.len());
+                } else {
+                    self.next_region();
+                }
+
+                let mapping = self.mapping.as_ref().unwrap();
+                let base_vertex = mapping.region * self.capacity;
+                ptr::copy_nonoverlapping(
+                    vertices.as_ptr() as *const u8,
+                    mapping.pointer.add(base_vertex * self.stride),
+                    mem::size_of_val(vertices),
+                );
+                self.base_vertex = base_vertex as i32;
+            } else {
+                // Orphan the old storage so that the driver does not have to synchronize
+                self.capacity = self.capacity.max(vertices.len());
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo.id());
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    (self.capacity * self.stride) as GLsizeiptr,
+                    ptr::null(),
+                    gl::STREAM_DRAW,
+                );
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    0,
+                    mem::size_of_val(vertices) as GLsizeiptr,
+                    vertices.as_ptr() as *const c_void,
+                );
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+        }
+
+        self.vertex_num = vertices.len() as i32;
+    }
+
+    fn check_format<V: VertexFormat>(&self) {
+        assert_eq!(
+            mem::size_of::<V>(),
+            self.stride,
+            "Vertex format does not match the buffer"
+        );
+    }
+
+    /// Fence the current region and move to the next one once the GPU has finished with it
+    unsafe fn next_region(&mut self) {
+        let mapping = self.mapping.as_mut().unwrap();
+
+        mapping.fences[mapping.region] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        mapping.region = (mapping.region + 1) % STREAM_REGION_NUM;
+        wait_fence(&mut mapping.fences[mapping.region]);
+    }
+
+    /// Wait until the GPU has finished the commands issued so far on the current region
+    unsafe fn finish_region(&mut self) {
+        let mapping = self.mapping.as_mut().unwrap();
+
+        mapping.fences[mapping.region] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        wait_fence(&mut mapping.fences[mapping.region]);
+    }
+
+    /// (Re)create immutable buffer storage for persistent streaming
+    unsafe fn allocate_persistent<V: VertexFormat>(&mut self, capacity: usize) {
+        // Immutable storage cannot be resized, so replace the whole buffer.
+        // Deleting the old buffer also unmaps it.
+        self.mapping = None;
+        self.vbo = Buffer::new("Vertex (persistent)");
+
+        let size = (capacity * self.stride * STREAM_REGION_NUM) as GLsizeiptr;
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+
+        gl::BindVertexArray(self.vao.id());
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo.id());
+        gl::BufferStorage(gl::ARRAY_BUFFER, size, ptr::null(), flags);
+        let pointer = gl::MapBufferRange(gl::ARRAY_BUFFER, 0, size, flags) as *mut u8;
+
+        // Attribute pointers refer to the buffer bound when they are set
+        set_attribute_pointers::<V>(0);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+
+        self.capacity = capacity;
+        self.base_vertex = 0;
+        self.mapping = Some(PersistentMapping {
+            pointer,
+            fences: [ptr::null(); STREAM_REGION_NUM],
+            region: 0,
+        });
+    }
+
+    /// Upload index buffer. After this, draw calls use `glDrawElements`.
+    pub fn set_indices(&mut self, indices: IndexData, usage: GLenum) {
+        let (size, data, index_type, index_num) = match indices {
+            IndexData::U16(indices) => (
+                mem::size_of_val(indices),
+                indices.as_ptr() as *const c_void,
+                gl::UNSIGNED_SHORT,
+                indices.len(),
+            ),
+            IndexData::U32(indices) => (
+                mem::size_of_val(indices),
+                indices.as_ptr() as *const c_void,
+                gl::UNSIGNED_INT,
+                indices.len(),
+            ),
+        };
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            let ebo = self
+                .ebo
+                .get_or_insert_with(|| Buffer::new("Vertex (index)"));
+
+            // Element array buffer binding is stored in vertex array object
+            gl::BindVertexArray(self.vao.id());
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo.id());
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size as GLsizeiptr, data, usage);
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        }
+
+        self.index_type = index_type;
+        self.index_num = index_num as i32;
+    }
+
+    /// Set 32-bit indices, stored as 16-bit ones when every index fits to halve the buffer
+    pub fn set_indices_compact(&mut self, indices: &[u32], usage: GLenum) {
+        if indices.iter().all(|index| *index <= u16::MAX as u32) {
+            let indices: Vec<u16> = indices.iter().map(|index| *index as u16).collect();
+            self.set_indices(IndexData::U16(&indices), usage);
+        } else {
+            self.set_indices(IndexData::U32(indices), usage);
+        }
+    }
+
+    /// Add a buffer of per-instance attributes and return its index.
+    /// Locations of `I` must not overlap the vertex attributes.
+    pub fn add_instances<I: VertexFormat>(&mut self, instances: &[I], usage: GLenum) -> usize {
+        let vbo = Buffer::new("Vertex (instance)");
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            gl::BindVertexArray(self.vao.id());
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                mem::size_of_val(instances) as GLsizeiptr,
+                instances.as_ptr() as *const c_void,
+                usage,
+            );
+
+            // Advance the attributes once per instance instead of once per vertex
+            set_attribute_pointers::<I>(1);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+
+        self.instance_buffers.push(InstanceBuffer {
+            vbo,
+            stride: mem::size_of::<I>(),
+            capacity: instances.len(),
+        });
+        self.instance_num = instances.len() as i32;
+
+        return self.instance_buffers.len() - 1;
+    }
+
+    /// Replace the contents of an instance buffer added by `add_instances`
+    pub fn update_instances<I: VertexFormat>(&mut self, buffer: usize, instances: &[I]) {
+        let instance_buffer = &mut self.instance_buffers[buffer];
+        assert_eq!(
+            mem::size_of::<I>(),
+            instance_buffer.stride,
+            "Instance format does not match the buffer"
+        );
+
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, instance_buffer.vbo.id());
+            if instances.len() > instance_buffer.capacity {
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    mem::size_of_val(instances) as GLsizeiptr,
+                    instances.as_ptr() as *const c_void,
+                    gl::DYNAMIC_DRAW,
+                );
+                instance_buffer.capacity = instances.len();
+            } else {
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    0,
+                    mem::size_of_val(instances) as GLsizeiptr,
+                    instances.as_ptr() as *const c_void,
+                );
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+
+        self.instance_num = instances.len() as i32;
+    }
+
+    pub fn instance_num(&self) -> i32 {
+        return self.instance_num;
+    }
+
+    /// Set primitive topology (gl::TRIANGLES, gl::TRIANGLE_STRIP, gl::LINES, gl::LINE_STRIP, gl::POINTS, ...)
+    pub fn set_mode(&mut self, mode: GLenum) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> GLenum {
+        return self.mode;
+    }
+
+    /// Bind the vertex buffer to the shader storage `binding`, so that a compute
+    /// shader can read and write the vertices
+    pub fn bind_storage(&self, binding: u32) {
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.vbo.id());
+        }
+    }
+
+    /// Number of elements drawn by `draw` (indices if indexed, otherwise vertices)
+    pub fn element_num(&self) -> i32 {
+        if self.ebo.is_some() {
+            return self.index_num;
         }
+
+        return self.vertex_num;
     }
 
     pub fn draw(&self) {
+        self.draw_range(0, self.element_num());
+    }
+
+    /// Draw `count` elements starting at `first` (an index offset if indexed)
+    pub fn draw_range(&self, first: i32, count: i32) {
         // Use unsafe block to use OpenGL functions
         unsafe {
-            gl::BindVertexArray(self.vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_num);
+            gl::BindVertexArray(self.vao.id());
+            if self.ebo.is_some() {
+                gl::DrawElementsBaseVertex(
+                    self.mode,
+                    count,
+                    self.index_type,
+                    self.index_offset(first),
+                    self.base_vertex,
+                );
+            } else {
+                gl::DrawArrays(self.mode, self.base_vertex + first, count);
+            }
             gl::BindVertexArray(0);
         }
     }
+
+    /// Draw `count` elements starting at `first` for each of `instance_num` instances
+    pub fn draw_range_instanced(&self, first: i32, count: i32, instance_num: i32) {
+        // Use unsafe block to use OpenGL functions
+        unsafe {
+            gl::BindVertexArray(self.vao.id());
+            if self.ebo.is_some() {
+                gl::DrawElementsInstancedBaseVertex(
+                    self.mode,
+                    count,
+                    self.index_type,
+                    self.index_offset(first),
+                    instance_num,
+                    self.base_vertex,
+                );
+            } else {
+                gl::DrawArraysInstanced(self.mode, self.base_vertex + first, count, instance_num);
+            }
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn index_offset(&self, first: i32) -> *const c_void {
+        let index_size = match self.index_type {
+            gl::UNSIGNED_SHORT => mem::size_of::<u16>(),
+            _ => mem::size_of::<u32>(),
+        };
+
+        return (first as usize * index_size) as *const c_void;
+    }
+}
+
+/// Whether the context has immutable buffer storage for persistent streaming
+/// Block until the fence is signaled and delete it. Does nothing for a null fence.
+unsafe fn wait_fence(fence: &mut GLsync) {
+    if fence.is_null() {
+        return;
+    }
+
+    while gl::ClientWaitSync(*fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000) == gl::TIMEOUT_EXPIRED
+    {
+    }
+    gl::DeleteSync(*fence);
+    *fence = ptr::null();
+}
+
+fn supports_buffer_storage() -> bool {
+    return gl::BufferStorage::is_loaded() && gl_supports((4, 4), "GL_ARB_buffer_storage");
+}
+
+/// Describe attributes of `V` for the buffer bound to `GL_ARRAY_BUFFER`.
+/// `divisor` is 0 for per-vertex attributes and 1 for per-instance attributes.
+unsafe fn set_attribute_pointers<V: VertexFormat>(divisor: u32) {
+    let stride = mem::size_of::<V>() as GLsizei;
+
+    for attribute in V::attributes() {
+        let column_size = attribute.components as usize * component_size(attribute.gl_type);
+
+        for column in 0..attribute.locations {
+            let location = attribute.location + column;
+            let pointer = (attribute.offset + column as usize * column_size) as *const c_void;
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribDivisor(location, divisor);
+
+            // Integer attributes are passed to ivec / uvec inputs unless normalized
+            match attribute.gl_type {
+                gl::FLOAT => gl::VertexAttribPointer(
+                    location,
+                    attribute.components,
+                    attribute.gl_type,
+                    gl::FALSE,
+                    stride,
+                    pointer,
+                ),
+                gl::DOUBLE => gl::VertexAttribLPointer(
+                    location,
+                    attribute.components,
+                    attribute.gl_type,
+                    stride,
+                    pointer,
+                ),
+                _ if attribute.normalized => gl::VertexAttribPointer(
+                    location,
+                    attribute.components,
+                    attribute.gl_type,
+                    gl::TRUE,
+                    stride,
+                    pointer,
+                ),
+                _ => gl::VertexAttribIPointer(
+                    location,
+                    attribute.components,
+                    attribute.gl_type,
+                    stride,
+                    pointer,
+                ),
+            }
+        }
+    }
+}
+
+fn component_size(gl_type: GLenum) -> usize {
+    return match gl_type {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT => 2,
+        gl::DOUBLE => 8,
+        _ => 4,
+    };
 }